@@ -1,13 +1,12 @@
-use astroport::asset::AssetInfo;
-use cosmwasm_std::{
-    to_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Env, Response, StdError, Uint128, WasmMsg,
-};
-use cw20::Cw20ExecuteMsg;
+use cosmwasm_std::{Addr, CosmosMsg, DepsMut, Env, Response, StdError, Uint128};
 use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
 
 use crate::{
+    fees::mint_lp_msg,
+    helpers::payout_msg,
+    ledger::finish_operation,
     querier::query_token_info,
-    state::{ClobCache, CLOB_CACHE, CONFIG},
+    state::{OperationKind, CONFIG},
 };
 
 pub fn after_deposit(
@@ -16,32 +15,38 @@ pub fn after_deposit(
     deposit: Uint128,
     sender: Addr,
     basket_value_before_deposit: Uint128,
+    min_lp_out: Option<Uint128>,
 ) -> Result<Response<InjectiveMsgWrapper>, StdError> {
     let config = CONFIG.load(deps.storage)?;
-    let clob_cache: Vec<ClobCache> = CLOB_CACHE.load(deps.storage)?;
-    let paid: Uint128 = clob_cache.into_iter().try_fold(Uint128::zero(), |acc, c| {
-        acc.checked_add((c.quantity * c.price + c.fee).add(1).into())
-    })?;
+    let operation = finish_operation(deps.storage, OperationKind::Deposit)?;
+    let failed_assets: Vec<String> = operation
+        .failed_assets()
+        .iter()
+        .map(|asset| asset.to_string())
+        .collect();
 
-    let leftover = deposit.checked_sub(paid).unwrap_or(Uint128::zero());
+    // The failed orders are refunded with the leftover under the `Refund` order failure policy
+    let paid = operation.paid()?;
 
-    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    // The orders only spent the deposit net of the entry fee, which is charged on what they paid
+    let unspent = deposit.checked_sub(paid).unwrap_or(Uint128::zero());
+    let entry_fee = config
+        .fees
+        .entry_fee_on_invested(&sender, paid)
+        .min(unspent);
+    let leftover = unspent.checked_sub(entry_fee)?;
 
-    if let AssetInfo::NativeToken { denom } = config.deposit_asset {
-        if leftover.gt(&Uint128::zero()) {
-            let leftover_coins = Coin::new(u128::from(leftover), denom);
+    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
 
-            let send_message = BankMsg::Send {
-                to_address: sender.clone().into_string(),
-                amount: vec![leftover_coins],
-            };
+    if leftover.gt(&Uint128::zero()) {
+        messages.push(payout_msg(&config, &sender, leftover)?);
+    }
 
-            messages.push(send_message.into());
-        }
+    if !entry_fee.is_zero() {
+        messages.push(payout_msg(&config, &config.fees.collector, entry_fee)?);
     }
 
-    let liquidity_token = config.lp_token;
-    let total_share = query_token_info(&deps.querier, &liquidity_token)?.total_supply;
+    let total_share = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
 
     let lp_amount = if total_share.is_zero() {
         paid
@@ -50,28 +55,31 @@ pub fn after_deposit(
             .checked_div(basket_value_before_deposit)?
     };
 
-    messages.push(
-        WasmMsg::Execute {
-            contract_addr: liquidity_token.into_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Mint {
-                recipient: sender.into_string(),
-                amount: lp_amount,
-            })?,
-            funds: vec![],
+    messages.push(mint_lp_msg(&config, &sender, lp_amount)?);
+
+    // Reverts the whole deposit, orders included
+    if let Some(min_lp_out) = min_lp_out {
+        if lp_amount < min_lp_out {
+            return Err(StdError::generic_err(format!(
+                "Minimum LP amount not reached: {} < {}",
+                lp_amount, min_lp_out
+            )));
         }
-        .into(),
-    );
+    }
 
     Ok(Response::new()
         .add_attributes(vec![
             ("method", "after_deposit".to_string()),
+            ("operation_id", operation.id.to_string()),
             ("deposit", deposit.to_string()),
             ("paid", paid.to_string()),
+            ("failed_assets", failed_assets.join(",")),
             ("total_share", total_share.to_string()),
             (
                 "basket_value_before_deposit",
                 basket_value_before_deposit.to_string(),
             ),
+            ("entry_fee", entry_fee.to_string()),
             ("lp_amount", lp_amount.to_string()),
         ])
         .add_messages(messages))
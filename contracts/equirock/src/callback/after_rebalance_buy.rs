@@ -0,0 +1,35 @@
+use cosmwasm_std::{DepsMut, Env, Response, StdError};
+
+use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
+
+use crate::{
+    ledger::finish_operation,
+    query::tracking_error,
+    state::{OperationKind, BASKET, CONFIG},
+};
+
+pub fn after_rebalance_buy(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+) -> Result<Response<InjectiveMsgWrapper>, StdError> {
+    let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
+
+    let operation = finish_operation(deps.storage, OperationKind::Rebalance)?;
+    let paid = operation.paid()?;
+
+    let tracking_error = tracking_error(
+        &deps.querier,
+        &env,
+        &config,
+        &basket,
+        config.pricing.pricing(OperationKind::Rebalance),
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("method", "after_rebalance_buy".to_string()),
+        ("operation_id", operation.id.to_string()),
+        ("paid", paid.to_string()),
+        ("tracking_error", tracking_error.to_string()),
+    ]))
+}
@@ -1,56 +1,211 @@
-use astroport::asset::{Asset, AssetInfo};
 use cosmwasm_std::{
-    to_binary, Coin, CosmosMsg, DepsMut, Env, Response, StdError, Uint128, WasmMsg,
+    to_binary, Addr, CosmosMsg, Decimal, DepsMut, Env, Event, Response, StdError, SubMsg, Uint128,
+    WasmMsg,
 };
 
-use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
+use injective_cosmwasm::{
+    get_default_subaccount_id_for_checked_address, InjectiveMsgWrapper, InjectiveQuerier,
+    InjectiveQueryWrapper, OrderType,
+};
 
 use crate::{
-    msg::ExecuteMsg,
-    state::{ClobCache, CLOB_CACHE, CONFIG},
+    execute::spot_order,
+    helpers::payout_msg,
+    ledger::{operation_in_progress, record_order},
+    msg::{CallbackMsg, ExecuteMsg},
+    oracle::check_oracle_deviation,
+    orderbook::depth_limited_quantity,
+    querier::{query_decimals, query_holdings},
+    query::{basket_asset_limit_price, get_basket_ideal_ratio},
+    reply::order_submsg,
+    slippage::buy_slippage,
+    state::{OperationKind, OrderSide, BASKET, CONFIG},
+    ContractError,
 };
 
+/// Pays the keeper bounty, then buys the underweight basket assets with the rest of the proceeds
+/// of the rebalance sales, in proportion to how far each of them is below its target.
 pub fn after_rebalance_sell(
     deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
-) -> Result<Response<InjectiveMsgWrapper>, StdError> {
+    basket_value: Uint128,
+    keeper: Addr,
+    bounty: Uint128,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
 
-    let clob_cache: Vec<ClobCache> = CLOB_CACHE.load(deps.storage)?;
-
-    let received: Uint128 = clob_cache
-        .to_owned()
-        .into_iter()
-        .try_fold(Uint128::zero(), |acc, c| {
-            acc.checked_add((c.quantity * c.price - c.fee).sub(1).into())
-        })?;
+    // The buys are recorded in the same operation as the sales
+    let operation = operation_in_progress(deps.storage, OperationKind::Rebalance)?;
+    let received = operation.received()?;
 
+    let bounty = bounty.min(received);
     let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    if !bounty.is_zero() {
+        messages.push(payout_msg(&config, &keeper, bounty)?);
+    }
+
+    let usdt_decimals = 6u64;
+    let basket_value = Decimal::from_atomics(basket_value, usdt_decimals as u32)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let budget = Decimal::from_atomics(received - bounty, usdt_decimals as u32)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+
+    let contract = &env.contract.address;
+    let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
+    let injective_querier = InjectiveQuerier::new(&deps.querier);
+
+    // Quantity missing to reach the target, for each underweight asset
+    let mut underweights = vec![];
+    for (index, asset_ideal) in get_basket_ideal_ratio(
+        deps.as_ref(),
+        &env,
+        config.pricing.pricing(OperationKind::Rebalance),
+    )?
+    .into_iter()
+    .enumerate()
+    {
+        if asset_ideal.ratio.is_zero() {
+            continue;
+        }
+
+        let amount = query_holdings(
+            &deps.querier,
+            &asset_ideal.basket_asset.asset.info,
+            contract,
+        )?;
+        let decimals = query_decimals(&deps.querier, &asset_ideal.basket_asset.asset.info);
+        let current_quantity = Decimal::from_atomics(amount, decimals as u32)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+
+        let ideal_quantity = asset_ideal.ratio.checked_mul(basket_value)?;
+
+        if ideal_quantity <= current_quantity {
+            continue;
+        }
+
+        // Left alone by the rebalance if its weight is within the band of the target one
+        let missing_quantity = ideal_quantity - current_quantity;
+        let drift_band = asset_ideal.basket_asset.drift_band(basket.drift_band_bps);
+        if asset_ideal.price.checked_mul(missing_quantity)?
+            <= drift_band.checked_mul(basket_value)?
+        {
+            continue;
+        }
 
-    if let AssetInfo::NativeToken { denom } = &config.deposit_asset {
-        if received.gt(&Uint128::zero()) {
-            let received_coins = Coin::new(u128::from(received), denom);
-
-            let deposit_msg = WasmMsg::Execute {
-                contract_addr: env.contract.address.into_string(),
-                msg: to_binary(&ExecuteMsg::Deposit {
-                    asset: Asset {
-                        amount: received,
-                        info: config.deposit_asset,
-                    },
-                })?,
-                funds: vec![received_coins],
+        underweights.push((index, asset_ideal, decimals, missing_quantity));
+    }
+
+    let missing_value =
+        underweights
+            .iter()
+            .try_fold(Decimal::zero(), |acc, (_, asset_ideal, _, quantity)| {
+                acc.checked_add(asset_ideal.price.checked_mul(*quantity)?)
+            })?;
+
+    // Scaled down when the proceeds don't cover every underweight asset
+    let fill_ratio = if missing_value.is_zero() || budget >= missing_value {
+        Decimal::one()
+    } else {
+        budget
+            .checked_div(missing_value)
+            .map_err(|e| StdError::generic_err(e.to_string()))?
+    };
+
+    let mut submessages: Vec<SubMsg<InjectiveMsgWrapper>> = vec![];
+    let mut log: Vec<String> = vec![];
+    let mut events: Vec<Event> = vec![];
+
+    if !budget.is_zero() {
+        for (index, asset_ideal, decimals, missing_quantity) in underweights {
+            let market = injective_querier
+                .query_spot_market(&asset_ideal.basket_asset.spot_market_id)?
+                .market;
+            let market = match market {
+                Some(market) => market,
+                None => continue,
             };
 
-            messages.push(deposit_msg.into());
+            let slippage = asset_ideal.basket_asset.slippage(&config.slippage);
+            let mut quantity = missing_quantity.checked_mul(fill_ratio)?;
+
+            if let Some(max_price_impact_bps) = slippage.max_price_impact_bps {
+                quantity = depth_limited_quantity(
+                    &deps.querier,
+                    &market,
+                    &OrderType::BuyAtomic,
+                    quantity,
+                    decimals,
+                    max_price_impact_bps,
+                )?;
+            }
+
+            if quantity.is_zero() {
+                continue;
+            }
+
+            if let Some(event) = check_oracle_deviation(
+                &deps.querier,
+                &env,
+                &config,
+                &asset_ideal.basket_asset,
+                &market,
+                decimals,
+            )? {
+                events.push(event);
+            }
+
+            let limit_price = basket_asset_limit_price(
+                &deps.querier,
+                &env,
+                &config,
+                &asset_ideal.basket_asset,
+                OperationKind::Rebalance,
+                OrderSide::Buy,
+            )?;
+
+            let order_msg = spot_order(
+                buy_slippage(slippage.rebalance_bps)?,
+                limit_price,
+                quantity,
+                &market,
+                decimals,
+                usdt_decimals,
+                OrderType::BuyAtomic,
+                &subaccount_id,
+                contract,
+            )?;
+
+            log.push(format!("order_msg {:?}", order_msg));
+
+            record_order(
+                deps.storage,
+                &asset_ideal.basket_asset.asset.info,
+                &market.market_id,
+                OrderSide::Buy,
+            )?;
+            submessages.push(order_submsg(order_msg, index));
         }
     }
 
+    let after_rebalance_buy_msg = WasmMsg::Execute {
+        contract_addr: contract.to_string(),
+        msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::AfterRebalanceBuy {}))?,
+        funds: vec![],
+    };
+
     Ok(Response::new()
         .add_attributes(vec![
             ("method", "after_rebalance_sell".to_string()),
-            ("clob_cache", format!("{:?}", clob_cache)),
+            ("operation_id", operation.id.to_string()),
             ("received", received.to_string()),
+            ("keeper", keeper.to_string()),
+            ("bounty", bounty.to_string()),
+            ("fill_ratio", fill_ratio.to_string()),
+            ("log", format!("{:?}", log)),
         ])
-        .add_messages(messages))
+        .add_events(events)
+        .add_messages(messages)
+        .add_submessages(submessages)
+        .add_message(after_rebalance_buy_msg))
 }
@@ -1,45 +1,61 @@
-use astroport::asset::AssetInfo;
-use cosmwasm_std::{Addr, BankMsg, Coin, CosmosMsg, DepsMut, Env, Response, StdError, Uint128};
+use cosmwasm_std::{Addr, CosmosMsg, DepsMut, Env, Response, StdError, Uint128};
 
 use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
 
-use crate::state::{ClobCache, CLOB_CACHE, CONFIG};
+use crate::{
+    helpers::payout_msg,
+    ledger::finish_operation,
+    state::{OperationKind, CONFIG},
+};
 
 pub fn after_withdraw(
     deps: DepsMut<InjectiveQueryWrapper>,
     _env: Env,
     sender: Addr,
+    min_received: Option<Uint128>,
 ) -> Result<Response<InjectiveMsgWrapper>, StdError> {
     let config = CONFIG.load(deps.storage)?;
 
-    let clob_cache: Vec<ClobCache> = CLOB_CACHE.load(deps.storage)?;
+    let operation = finish_operation(deps.storage, OperationKind::Withdraw)?;
 
-    let received: Uint128 = clob_cache
-        .to_owned()
-        .into_iter()
-        .try_fold(Uint128::zero(), |acc, c| {
-            acc.checked_add((c.quantity * c.price - c.fee).sub(1).into())
-        })?;
-
-    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    // The user's share of the asset would stay in the vault
+    if let Some(failed_asset) = operation.failed_assets().first() {
+        return Err(StdError::generic_err(format!(
+            "Sale of {} failed, use WithdrawInKind",
+            failed_asset
+        )));
+    }
 
-    if let AssetInfo::NativeToken { denom } = config.deposit_asset {
-        if received.gt(&Uint128::zero()) {
-            let received_coins = Coin::new(u128::from(received), denom);
+    let received = operation.received()?;
 
-            let send_message = BankMsg::Send {
-                to_address: sender.clone().into_string(),
-                amount: vec![received_coins],
-            };
+    let exit_fee = config.fees.exit_fee(&sender, received);
+    let received = received.checked_sub(exit_fee)?;
 
-            messages.push(send_message.into());
+    // Reverts the whole withdrawal, orders included
+    if let Some(min_received) = min_received {
+        if received < min_received {
+            return Err(StdError::generic_err(format!(
+                "Minimum received amount not reached: {} < {}",
+                received, min_received
+            )));
         }
     }
 
+    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+
+    if !exit_fee.is_zero() {
+        messages.push(payout_msg(&config, &config.fees.collector, exit_fee)?);
+    }
+
+    if received.gt(&Uint128::zero()) {
+        messages.push(payout_msg(&config, &sender, received)?);
+    }
+
     Ok(Response::new()
         .add_attributes(vec![
             ("method", "after_withdraw".to_string()),
-            ("clob_cache", format!("{:?}", clob_cache)),
+            ("operation_id", operation.id.to_string()),
+            ("exit_fee", exit_fee.to_string()),
             ("received", received.to_string()),
         ])
         .add_messages(messages))
@@ -7,21 +7,22 @@ pub use after_withdraw::after_withdraw;
 mod after_rebalance_sell;
 pub use after_rebalance_sell::after_rebalance_sell;
 
-use cosmwasm_std::{DepsMut, Env, MessageInfo, Response, StdError};
+mod after_rebalance_buy;
+pub use after_rebalance_buy::after_rebalance_buy;
+
+use cosmwasm_std::{DepsMut, Env, MessageInfo, Response};
 use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
 
-use crate::msg::CallbackMsg;
+use crate::{msg::CallbackMsg, ContractError};
 
 pub fn callback(
     deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
     info: MessageInfo,
     msg: CallbackMsg,
-) -> Result<Response<InjectiveMsgWrapper>, StdError> {
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     if info.sender != env.contract.address {
-        return Err(StdError::GenericErr {
-            msg: "Unauthorized".to_string(),
-        });
+        return Err(ContractError::Unauthorized {});
     }
 
     match msg {
@@ -29,9 +30,25 @@ pub fn callback(
             deposit,
             sender,
             basket_value,
-        } => after_deposit(deps, env, deposit, sender, basket_value),
-        CallbackMsg::AfterWithdraw { sender } => after_withdraw(deps, env, sender),
-        CallbackMsg::AfterRebalanceSell {} => after_rebalance_sell(deps, env),
+            min_lp_out,
+        } => Ok(after_deposit(
+            deps,
+            env,
+            deposit,
+            sender,
+            basket_value,
+            min_lp_out,
+        )?),
+        CallbackMsg::AfterWithdraw {
+            sender,
+            min_received,
+        } => Ok(after_withdraw(deps, env, sender, min_received)?),
+        CallbackMsg::AfterRebalanceSell {
+            basket_value,
+            keeper,
+            bounty,
+        } => after_rebalance_sell(deps, env, basket_value, keeper, bounty),
+        CallbackMsg::AfterRebalanceBuy {} => Ok(after_rebalance_buy(deps, env)?),
     }
 }
 
@@ -39,15 +56,30 @@ pub fn callback(
 mod test {
     use std::time::Duration;
 
-    use astroport::asset::AssetInfo;
-    use cosmwasm_std::{coins, testing::mock_info, Addr, Coin, Uint128};
-    use pyth_sdk_cw::testing::MockPyth;
+    use astroport::asset::{Asset, AssetInfo};
+    use cosmwasm_std::{
+        coins, from_binary, testing::mock_info, to_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut,
+        QuerierWrapper, Reply, Storage, SubMsgResult, Timestamp, Uint128, WasmMsg,
+    };
+    use cw20::Cw20ExecuteMsg;
+    use injective_cosmwasm::{InjectiveMsg, InjectiveMsgWrapper, MarketId, OrderType};
+    use injective_math::FPDecimal;
+    use pyth_sdk_cw::{testing::MockPyth, Price, PriceFeed, PriceIdentifier};
 
     use crate::{
-        contract::execute,
-        msg::{CallbackMsg, ExecuteMsg},
-        state::{ClobCache, Config, CLOB_CACHE, CONFIG},
-        tests::{setup_test, CONTRACT_ADDR, LP_TOKEN_ADDR, USDT},
+        contract::{execute, reply},
+        helpers::get_message_data,
+        msg::{CallbackMsg, Cw20AdapterMsg, ExecuteMsg},
+        state::{
+            Basket, BasketAsset, Config, FeeConfig, Fill, FillStatus, Operation, OperationKind,
+            OrderFailurePolicy, OrderSide, BASKET, CONFIG, OPERATIONS, OPERATION_ID,
+            OPERATION_IN_PROGRESS,
+        },
+        tests::{
+            create_balance_bank_handler, mock_config, setup_test, ATOM, ATOMUSDT_MARKET_ID,
+            CONTRACT_ADDR, INJUSDT_MARKET_ID, LP_TOKEN_ADDR, PRICE_ID_ATOM, PRICE_ID_INJ,
+            PYTH_CONTRACT_ADDR, USDT,
+        },
     };
 
     #[test]
@@ -62,6 +94,7 @@ mod test {
             deposit: Uint128::one(),
             sender: Addr::unchecked("sender"),
             basket_value: Uint128::one(),
+            min_lp_out: None,
         });
 
         let _res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
@@ -78,30 +111,557 @@ mod test {
             deposit: Uint128::one(),
             sender: Addr::unchecked("sender"),
             basket_value: Uint128::one(),
+            min_lp_out: None,
         });
+        CONFIG.save(&mut deps.storage, &mock_config()).unwrap();
 
-        let mock_address = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
+        save_operation(&mut deps.storage, OperationKind::Deposit, vec![]);
 
-        CONFIG
+        println!(
+            "{:?}",
+            execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap()
+        );
+    }
+
+    fn fee_config(entry_fee_bps: u64, exit_fee_bps: u64) -> Config {
+        Config {
+            fees: FeeConfig {
+                entry_fee_bps,
+                exit_fee_bps,
+                fee_exempt: vec![Addr::unchecked("market-maker")],
+                ..mock_config().fees
+            },
+            ..mock_config()
+        }
+    }
+
+    fn fill(denom: &str, market_id: &str, side: OrderSide, quantity: u128, price: u128) -> Fill {
+        Fill {
+            asset: AssetInfo::NativeToken {
+                denom: denom.to_owned(),
+            },
+            market_id: MarketId::new(market_id).unwrap(),
+            side,
+            quantity: FPDecimal::from(quantity),
+            price: FPDecimal::from(price),
+            fee: FPDecimal::zero(),
+            status: FillStatus::Filled,
+        }
+    }
+
+    fn save_operation(storage: &mut dyn Storage, kind: OperationKind, fills: Vec<Fill>) {
+        OPERATION_ID.save(storage, &1).unwrap();
+        OPERATION_IN_PROGRESS.save(storage, &1).unwrap();
+        OPERATIONS
+            .save(
+                storage,
+                1,
+                &Operation {
+                    id: 1,
+                    kind,
+                    initiator: Addr::unchecked("sender"),
+                    started_at: Timestamp::from_seconds(0),
+                    fills,
+                },
+            )
+            .unwrap();
+    }
+
+    fn mint_msg(recipient: &str, amount: u128) -> CosmosMsg<InjectiveMsgWrapper> {
+        WasmMsg::Execute {
+            contract_addr: LP_TOKEN_ADDR.to_owned(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: recipient.to_owned(),
+                amount: Uint128::new(amount),
+            })
+            .unwrap(),
+            funds: vec![],
+        }
+        .into()
+    }
+
+    #[test]
+    fn entry_fee() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        CONFIG.save(&mut deps.storage, &fee_config(100, 0)).unwrap();
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Deposit,
+            vec![fill("inj", INJUSDT_MARKET_ID, OrderSide::Buy, 1_000, 1)],
+        );
+
+        // 1_001 paid for 10_010 LP tokens, and 1% of the 1_011 deposited to the collector
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterDeposit {
+            deposit: Uint128::new(1_011),
+            sender: Addr::unchecked("sender"),
+            basket_value: Uint128::new(10),
+            min_lp_out: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("collector"),
+                    amount: coins(10, USDT),
+                }),
+                mint_msg("sender", 10_010)
+            ]
+        );
+
+        // Exempt addresses get the unspent deposit back
+        OPERATION_IN_PROGRESS.save(&mut deps.storage, &1).unwrap();
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterDeposit {
+            deposit: Uint128::new(1_011),
+            sender: Addr::unchecked("market-maker"),
+            basket_value: Uint128::new(10),
+            min_lp_out: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("market-maker"),
+                    amount: coins(10, USDT),
+                }),
+                mint_msg("market-maker", 10_010)
+            ]
+        );
+    }
+
+    #[test]
+    fn min_lp_out() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        CONFIG.save(&mut deps.storage, &fee_config(100, 0)).unwrap();
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Deposit,
+            vec![fill("inj", INJUSDT_MARKET_ID, OrderSide::Buy, 1_000, 1)],
+        );
+
+        // The sender gets 10_010 LP tokens, the entry fee is paid out of the deposit
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterDeposit {
+            deposit: Uint128::new(1_011),
+            sender: Addr::unchecked("sender"),
+            basket_value: Uint128::new(10),
+            min_lp_out: Some(Uint128::new(10_010)),
+        });
+        execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+        OPERATION_IN_PROGRESS.save(&mut deps.storage, &1).unwrap();
+
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterDeposit {
+            deposit: Uint128::new(1_011),
+            sender: Addr::unchecked("sender"),
+            basket_value: Uint128::new(10),
+            min_lp_out: Some(Uint128::new(10_011)),
+        });
+        let err = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Generic error: Minimum LP amount not reached: 10010 < 10011"
+        );
+    }
+
+    #[test]
+    fn exit_fee() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        CONFIG.save(&mut deps.storage, &fee_config(0, 100)).unwrap();
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Withdraw,
+            vec![fill("inj", INJUSDT_MARKET_ID, OrderSide::Sell, 1_000, 1)],
+        );
+
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
+            sender: Addr::unchecked("sender"),
+            min_received: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        // 1% of the 999 received goes to the collector
+        let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("collector"),
+                    amount: coins(9, USDT),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("sender"),
+                    amount: coins(990, USDT),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn min_received() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        CONFIG.save(&mut deps.storage, &fee_config(0, 100)).unwrap();
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Withdraw,
+            vec![fill("inj", INJUSDT_MARKET_ID, OrderSide::Sell, 1_000, 1)],
+        );
+
+        // The sender receives 990 once the exit fee is deducted
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
+            sender: Addr::unchecked("sender"),
+            min_received: Some(Uint128::new(990)),
+        });
+        execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+        OPERATION_IN_PROGRESS.save(&mut deps.storage, &1).unwrap();
+
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
+            sender: Addr::unchecked("sender"),
+            min_received: Some(Uint128::new(991)),
+        });
+        let err = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Generic error: Minimum received amount not reached: 990 < 991"
+        );
+    }
+
+    #[test]
+    fn after_withdraw_token() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        let mut config = fee_config(0, 0);
+        config.deposit_asset = AssetInfo::Token {
+            contract_addr: Addr::unchecked("usdt-token"),
+        };
+        config.cw20_adapter = Some(Addr::unchecked("cw20-adapter"));
+        CONFIG.save(&mut deps.storage, &config).unwrap();
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Withdraw,
+            vec![fill("inj", INJUSDT_MARKET_ID, OrderSide::Sell, 1_000, 1)],
+        );
+
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
+            sender: Addr::unchecked("sender"),
+            min_received: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("cw20-adapter"),
+                msg: to_binary(&Cw20AdapterMsg::RedeemAndTransfer {
+                    recipient: Some(String::from("sender")),
+                })
+                .unwrap(),
+                funds: coins(999, "factory/cw20-adapter/usdt-token"),
+            })]
+        );
+    }
+
+    #[test]
+    fn failed_order() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        BASKET
             .save(
                 &mut deps.storage,
-                &Config {
-                    lp_token: mock_address,
-                    deposit_asset: AssetInfo::NativeToken {
-                        denom: USDT.to_owned(),
-                    },
-                    pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
+                &Basket {
+                    assets: vec![
+                        basket_asset("inj", PRICE_ID_INJ, INJUSDT_MARKET_ID),
+                        basket_asset(ATOM, PRICE_ID_ATOM, ATOMUSDT_MARKET_ID),
+                    ],
+                    drift_band_bps: 0,
                 },
             )
             .unwrap();
+        let mut config = fee_config(0, 0);
+        CONFIG.save(&mut deps.storage, &config).unwrap();
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Deposit,
+            vec![
+                fill("inj", INJUSDT_MARKET_ID, OrderSide::Buy, 1_000, 1),
+                Fill {
+                    status: FillStatus::Pending,
+                    ..fill(ATOM, ATOMUSDT_MARKET_ID, OrderSide::Buy, 0, 0)
+                },
+            ],
+        );
+
+        // The ATOM order fails
+        let failed_reply = Reply {
+            id: 1_001,
+            result: SubMsgResult::Err("insufficient liquidity".to_owned()),
+        };
+        let err = reply(
+            DepsMut {
+                storage: &mut deps.storage,
+                api: &deps.api,
+                querier: QuerierWrapper::new(&deps.querier),
+            },
+            env.to_owned(),
+            failed_reply.to_owned(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failure response from submsg: insufficient liquidity"
+        );
 
-        CLOB_CACHE
-            .save(&mut deps.storage, &vec![ClobCache::new()])
+        config.order_failure = OrderFailurePolicy::Refund;
+        CONFIG.save(&mut deps.storage, &config).unwrap();
+        reply(
+            DepsMut {
+                storage: &mut deps.storage,
+                api: &deps.api,
+                querier: QuerierWrapper::new(&deps.querier),
+            },
+            env.to_owned(),
+            failed_reply,
+        )
+        .unwrap();
+
+        let operation = OPERATIONS.load(&deps.storage, 1).unwrap();
+        assert_eq!(operation.fills[1].status, FillStatus::Failed);
+
+        // Only the INJ order was paid, the rest of the deposit is refunded
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterDeposit {
+            deposit: Uint128::new(2_001),
+            sender: Addr::unchecked("sender"),
+            basket_value: Uint128::new(10),
+            min_lp_out: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("sender"),
+                    amount: coins(1_000, USDT),
+                }),
+                mint_msg("sender", 10_010),
+            ]
+        );
+
+        // Withdrawals revert whatever the policy
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Withdraw,
+            vec![Fill {
+                status: FillStatus::Failed,
+                ..fill(ATOM, ATOMUSDT_MARKET_ID, OrderSide::Sell, 0, 0)
+            }],
+        );
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
+            sender: Addr::unchecked("sender"),
+            min_received: None,
+        });
+        let err = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Generic error: Sale of {} failed, use WithdrawInKind", ATOM)
+        );
+    }
+
+    fn price_feed(id: &str, price: i64, publish_time: i64) -> PriceFeed {
+        let price = Price {
+            price,
+            conf: 10,
+            expo: -8,
+            publish_time,
+        };
+        PriceFeed::new(PriceIdentifier::from_hex(id).unwrap(), price, price)
+    }
+
+    fn basket_asset(denom: &str, price_id: &str, market_id: &str) -> BasketAsset {
+        BasketAsset {
+            asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: denom.to_owned(),
+                },
+                amount: Uint128::zero(),
+            },
+            pyth_price_feed: PriceIdentifier::from_hex(price_id).unwrap(),
+            weight: Uint128::one(),
+            spot_market_id: MarketId::new(market_id).unwrap(),
+            slippage: None,
+            drift_band_bps: None,
+            oracle: None,
+        }
+    }
+
+    #[test]
+    fn after_rebalance_sell() {
+        let current_unix_time = 10_000_000;
+        let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        mock_pyth.add_feed(price_feed(PRICE_ID_INJ, 800_000_000, current_unix_time));
+        mock_pyth.add_feed(price_feed(PRICE_ID_ATOM, 1_250_000_000, current_unix_time));
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        // 1 INJ worth 8 USDT is below its 10 USDT target, ATOM is above it
+        deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+            |denom| match denom {
+                "inj" => 1_000_000_000_000_000_000,
+                _ => 1_000_000_000,
+            },
+        ))));
+
+        let mut config = fee_config(0, 0);
+        config.pyth_contract_addr = Addr::unchecked(PYTH_CONTRACT_ADDR);
+        CONFIG.save(&mut deps.storage, &config).unwrap();
+        BASKET
+            .save(
+                &mut deps.storage,
+                &Basket {
+                    assets: vec![
+                        basket_asset("inj", PRICE_ID_INJ, INJUSDT_MARKET_ID),
+                        basket_asset(ATOM, PRICE_ID_ATOM, ATOMUSDT_MARKET_ID),
+                    ],
+                    drift_band_bps: 0,
+                },
+            )
             .unwrap();
 
-        println!(
-            "{:?}",
-            execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap()
+        // 10 USDT received from the sales
+        save_operation(
+            &mut deps.storage,
+            OperationKind::Rebalance,
+            vec![fill(
+                ATOM,
+                ATOMUSDT_MARKET_ID,
+                OrderSide::Sell,
+                1,
+                10_000_001,
+            )],
         );
+
+        let msg = ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell {
+            basket_value: Uint128::new(20_000_000),
+            keeper: Addr::unchecked("keeper"),
+            bounty: Uint128::new(1_000_000),
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(CONTRACT_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "keeper".to_string(),
+                amount: coins(1_000_000, USDT),
+            })
+        );
+
+        // Only the missing 0.25 INJ is bought, less the slippage
+        assert_eq!(res.messages.len(), 3);
+        if let InjectiveMsg::CreateSpotMarketOrder { order, .. } =
+            &get_message_data(&res.messages, 1).msg_data
+        {
+            assert_eq!(order.market_id.as_str(), INJUSDT_MARKET_ID);
+            assert_eq!(order.order_type, OrderType::BuyAtomic);
+            assert_eq!(
+                order.order_info.quantity,
+                FPDecimal::from(217_000_000_000_000_000u128)
+            );
+        } else {
+            panic!("Wrong message type!");
+        }
+
+        match &res.messages[2].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => assert_eq!(
+                from_binary::<ExecuteMsg>(msg).unwrap(),
+                ExecuteMsg::Callback(CallbackMsg::AfterRebalanceBuy {})
+            ),
+            _ => panic!("Wrong message type!"),
+        }
     }
 }
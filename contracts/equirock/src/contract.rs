@@ -1,9 +1,8 @@
-use astroport::asset::AssetInfo;
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Reply, ReplyOn, Response, StdError,
-    StdResult, SubMsg, Uint128, WasmMsg,
+    to_binary, Addr, Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Reply, ReplyOn, Response,
+    StdError, StdResult, SubMsg, Uint128, WasmMsg,
 };
 use cw2::set_contract_version;
 use cw20_base::msg::InstantiateMsg as CW20InstantiateMsg;
@@ -11,12 +10,25 @@ use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
 
 use crate::callback::callback;
 use crate::error::ContractError;
-use crate::execute::{deposit, rebalance, update_config};
+use crate::execute::{
+    claim_ownership, deposit, deposit_in_kind, drop_ownership_proposal, propose_new_owner,
+    rebalance, set_pause,
+};
+use crate::helpers::{check_quote_denom, quote_denom};
 use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
-use crate::query::{config, get_basket_ideal_ratio, get_basket_value};
+use crate::oracle::Pricing;
+use crate::query::{
+    config, get_asset_weights, get_basket_ideal_ratio, get_basket_value, get_feed_health,
+    get_operations, get_pending_changes, get_pending_fees, get_tracking_error, high_water_mark,
+    operation, pause_state, rebalance_plan,
+};
 use crate::receive::receive;
-use crate::reply::{handle_lp_init, handle_order, ATOMIC_ORDER_REPLY_ID, INSTANTIATE_REPLY_ID};
-use crate::state::{Config, BASKET, CONFIG};
+use crate::reply::{handle_lp_init, handle_order, INSTANTIATE_REPLY_ID, ORDER_REPLY_ID_OFFSET};
+use crate::state::{
+    Config, ConfigChange, HighWaterMark, BASKET, CONFIG, HIGH_WATER_MARK, LAST_FEE_ACCRUAL,
+};
+use crate::timelock::{apply_change, cancel_change, queue_change, queue_config_update};
+use crate::twap::abort_rebalance;
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:equirock-contract";
@@ -26,22 +38,52 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub fn instantiate(
     deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
-    if let AssetInfo::Token { .. } = &msg.deposit_asset {
-        return Err(StdError::generic_err("Tokens not supported").into());
+    msg.deposit_asset.check(deps.api)?;
+    msg.slippage.validate()?;
+    if let Some(twap) = &msg.twap {
+        twap.validate()?;
     }
+    msg.rebalance_mode.validate()?;
+    msg.oracle.validate()?;
 
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     let config = Config {
+        owner: info.sender,
         lp_token: Addr::unchecked(""),
         deposit_asset: msg.deposit_asset,
-        pyth_contract_addr: msg.pyth_contract_addr,
+        cw20_adapter: msg
+            .cw20_adapter
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        pyth_contract_addr: deps.api.addr_validate(&msg.pyth_contract_addr)?,
+        guardian: msg
+            .guardian
+            .map(|guardian| deps.api.addr_validate(&guardian))
+            .transpose()?,
+        timelock_delay: msg.timelock_delay,
+        fees: msg.fees.validate(deps.api)?,
+        slippage: msg.slippage,
+        keeper: msg.keeper.validate(deps.api)?,
+        twap: msg.twap,
+        rebalance_mode: msg.rebalance_mode,
+        order_failure: msg.order_failure,
+        oracle: msg.oracle,
+        pricing: msg.pricing,
     };
 
     CONFIG.save(deps.storage, &config)?;
+    LAST_FEE_ACCRUAL.save(deps.storage, &env.block.time)?;
+    HIGH_WATER_MARK.save(
+        deps.storage,
+        &HighWaterMark {
+            nav_per_share: Decimal::one(),
+            crystallized_at: env.block.time,
+        },
+    )?;
 
     if let Some(non_zero_basket_asset) = msg
         .basket
@@ -56,6 +98,26 @@ pub fn instantiate(
         .into());
     }
 
+    for slippage in msg.basket.assets.iter().filter_map(|b| b.slippage.as_ref()) {
+        slippage.validate()?;
+    }
+
+    for oracle in msg.basket.assets.iter().filter_map(|b| b.oracle.as_ref()) {
+        oracle.validate()?;
+    }
+
+    msg.basket.validate_drift_bands()?;
+
+    quote_denom(&config.deposit_asset, config.cw20_adapter.as_ref())?;
+    for basket_asset in msg.basket.assets.iter() {
+        check_quote_denom(
+            &deps.querier,
+            &config.deposit_asset,
+            config.cw20_adapter.as_ref(),
+            &basket_asset.spot_market_id,
+        )?;
+    }
+
     BASKET.save(deps.storage, &msg.basket)?;
 
     Ok(Response::new().add_submessage(SubMsg {
@@ -90,11 +152,62 @@ pub fn execute(
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> Result<Response<InjectiveMsgWrapper>, StdError> {
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     match msg {
-        ExecuteMsg::UpdateConfig {} => update_config(deps, info, None, None),
-        ExecuteMsg::Deposit { asset } => deposit(deps, env, info, asset),
+        ExecuteMsg::UpdateConfig(msg) => queue_config_update(deps, env, info, msg),
+        ExecuteMsg::ProposeNewOwner { owner, expires_in } => {
+            propose_new_owner(deps, env, info, owner, expires_in)
+        }
+        ExecuteMsg::DropOwnershipProposal {} => drop_ownership_proposal(deps, info),
+        ExecuteMsg::ClaimOwnership {} => claim_ownership(deps, env, info),
+        ExecuteMsg::UpdateAssetWeight { asset, weight } => queue_change(
+            deps,
+            env,
+            info,
+            ConfigChange::UpdateAssetWeight { asset, weight },
+        ),
+        ExecuteMsg::AddAsset { basket_asset } => {
+            queue_change(deps, env, info, ConfigChange::AddAsset { basket_asset })
+        }
+        ExecuteMsg::RetireAsset { asset } => {
+            queue_change(deps, env, info, ConfigChange::RetireAsset { asset })
+        }
+        ExecuteMsg::UpdateDriftBand {
+            asset,
+            drift_band_bps,
+        } => queue_change(
+            deps,
+            env,
+            info,
+            ConfigChange::UpdateDriftBand {
+                asset,
+                drift_band_bps,
+            },
+        ),
+        ExecuteMsg::SetPause {
+            deposit,
+            withdraw,
+            rebalance,
+        } => set_pause(deps, info, deposit, withdraw, rebalance),
+        ExecuteMsg::ApplyChange { id } => apply_change(deps, env, id),
+        ExecuteMsg::CancelChange { id } => cancel_change(deps, info, id),
+        ExecuteMsg::Deposit {
+            asset,
+            min_lp_out,
+            deadline,
+            max_slippage_bps,
+        } => deposit(
+            deps,
+            env,
+            info,
+            asset,
+            min_lp_out,
+            deadline,
+            max_slippage_bps,
+        ),
+        ExecuteMsg::DepositInKind {} => deposit_in_kind(deps, env, info),
         ExecuteMsg::Rebalance {} => rebalance(deps, env, info),
+        ExecuteMsg::AbortRebalance {} => abort_rebalance(deps, info),
         ExecuteMsg::Callback(msg) => callback(deps, env, info, msg),
         ExecuteMsg::Receive(msg) => receive(deps, env, info, msg),
     }
@@ -104,8 +217,25 @@ pub fn execute(
 pub fn query(deps: Deps<InjectiveQueryWrapper>, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetConfig {} => to_binary(&config(deps)?),
-        QueryMsg::GetBasketIdealRatio {} => to_binary(&get_basket_ideal_ratio(deps, &env)?),
+        QueryMsg::GetBasketIdealRatio {} => {
+            to_binary(&get_basket_ideal_ratio(deps, &env, Pricing::Spot)?)
+        }
         QueryMsg::GetBasketValueInUsdt {} => to_binary(&get_basket_value(deps, &env)?),
+        QueryMsg::GetPauseState {} => to_binary(&pause_state(deps)?),
+        QueryMsg::GetPendingFees {} => to_binary(&get_pending_fees(deps, &env)?),
+        QueryMsg::GetHighWaterMark {} => to_binary(&high_water_mark(deps)?),
+        QueryMsg::GetTrackingError {} => to_binary(&get_tracking_error(deps, &env)?),
+        QueryMsg::GetAssetWeights {} => to_binary(&get_asset_weights(deps, &env)?),
+        QueryMsg::GetRebalancePlan {} => to_binary(&rebalance_plan(deps)?),
+        QueryMsg::GetFeedHealth {} => to_binary(&get_feed_health(deps, &env)?),
+        QueryMsg::GetPendingChanges { start_after, limit } => {
+            to_binary(&get_pending_changes(deps, start_after, limit)?)
+        }
+        QueryMsg::GetOperation { id } => to_binary(&operation(deps, id)?),
+        QueryMsg::GetOperations {
+            start_before,
+            limit,
+        } => to_binary(&get_operations(deps, start_before, limit)?),
     }
 }
 
@@ -117,7 +247,7 @@ pub fn reply(
 ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     match msg.id {
         INSTANTIATE_REPLY_ID => handle_lp_init(deps, env, msg),
-        ATOMIC_ORDER_REPLY_ID => handle_order(deps, env, msg),
+        id if id >= ORDER_REPLY_ID_OFFSET => handle_order(deps, env, msg),
         _ => Err(ContractError::UnrecognisedReply(msg.id)),
     }
 }
@@ -0,0 +1,98 @@
+use cosmwasm_std::{Decimal, StdError, StdResult};
+
+use crate::{
+    fees::BPS_DENOMINATOR,
+    state::{Basket, BasketAsset},
+};
+
+/// Maximum drift band, half of the basket value.
+pub const MAX_DRIFT_BAND_BPS: u64 = 5_000;
+
+pub fn validate_drift_band(drift_band_bps: u64) -> StdResult<()> {
+    if drift_band_bps > MAX_DRIFT_BAND_BPS {
+        return Err(StdError::generic_err(format!(
+            "Drift band cannot exceed {} bps",
+            MAX_DRIFT_BAND_BPS
+        )));
+    }
+
+    Ok(())
+}
+
+impl Basket {
+    pub fn validate_drift_bands(&self) -> StdResult<()> {
+        validate_drift_band(self.drift_band_bps)?;
+
+        for drift_band_bps in self.assets.iter().filter_map(|b| b.drift_band_bps) {
+            validate_drift_band(drift_band_bps)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl BasketAsset {
+    /// Drift band of the asset as a share of the basket value, falling back to the basket one.
+    pub fn drift_band(&self, default_bps: u64) -> Decimal {
+        Decimal::from_ratio(self.drift_band_bps.unwrap_or(default_bps), BPS_DENOMINATOR)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use astroport::asset::{Asset, AssetInfo};
+    use cosmwasm_std::{Decimal, Uint128};
+    use injective_cosmwasm::MarketId;
+    use pyth_sdk_cw::PriceIdentifier;
+
+    use crate::{
+        state::{Basket, BasketAsset},
+        tests::{INJUSDT_MARKET_ID, PRICE_ID_INJ},
+    };
+
+    fn basket_asset(drift_band_bps: Option<u64>) -> BasketAsset {
+        BasketAsset {
+            asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "inj".to_owned(),
+                },
+                amount: Uint128::zero(),
+            },
+            weight: Uint128::one(),
+            pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+            spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+            slippage: None,
+            drift_band_bps,
+            oracle: None,
+        }
+    }
+
+    #[test]
+    fn drift_band_per_asset() {
+        assert_eq!(
+            basket_asset(None).drift_band(200),
+            Decimal::from_str("0.02").unwrap()
+        );
+        assert_eq!(
+            basket_asset(Some(500)).drift_band(200),
+            Decimal::from_str("0.05").unwrap()
+        );
+    }
+
+    #[test]
+    fn validate_drift_bands() {
+        let mut basket = Basket {
+            assets: vec![basket_asset(Some(5_000))],
+            drift_band_bps: 200,
+        };
+        basket.validate_drift_bands().unwrap();
+
+        basket.assets = vec![basket_asset(Some(5_001))];
+        assert_eq!(
+            basket.validate_drift_bands().unwrap_err().to_string(),
+            "Generic error: Drift band cannot exceed 5000 bps"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{OverflowError, StdError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,9 +6,51 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("Ownership proposal expired")]
+    OwnershipProposalExpired {},
+
+    #[error("Change {id} cannot be applied before {effective_at}")]
+    TimelockNotExpired { id: u64, effective_at: u64 },
+
+    #[error("Deposits are paused")]
+    DepositPaused {},
+
+    #[error("Rebalancing is paused")]
+    RebalancePaused {},
+
+    #[error("Rebalance is not allowed before {next_rebalance}")]
+    RebalanceCooldown { next_rebalance: u64 },
+
+    #[error("Basket is within its drift bands")]
+    WithinDriftBands {},
+
+    #[error("Operation {id} is in progress")]
+    OperationInProgress { id: u64 },
+
+    #[error("Price feed of {asset} is stale, published at {publish_time}")]
+    StalePrice { asset: String, publish_time: i64 },
+
+    #[error("Price feed of {asset} is too uncertain, confidence of {conf_bps} bps")]
+    UncertainPrice { asset: String, conf_bps: u64 },
+
+    #[error("Oracle price {oracle_price} of {asset} is {deviation_bps} bps away from the mid price {mid_price} of market {market_id}")]
+    OracleDeviation {
+        asset: String,
+        market_id: String,
+        oracle_price: String,
+        mid_price: String,
+        deviation_bps: u64,
+    },
+
+    #[error("Deadline {deadline} expired")]
+    DeadlineExpired { deadline: u64 },
+
     #[error("Unrecognised reply id: {0}")]
     UnrecognisedReply(u64),
 
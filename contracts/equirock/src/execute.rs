@@ -5,8 +5,8 @@ use std::{
 
 use astroport::asset::{Asset, AssetInfo};
 use cosmwasm_std::{
-    to_binary, Addr, CosmosMsg, Decimal, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
-    SubMsg, WasmMsg,
+    to_binary, Addr, CosmosMsg, Decimal, DepsMut, Env, Event, MessageInfo, QuerierWrapper,
+    Response, StdError, StdResult, SubMsg, Timestamp, Uint128, WasmMsg,
 };
 use injective_cosmwasm::{
     create_spot_market_order_msg, get_default_subaccount_id_for_checked_address,
@@ -16,22 +16,384 @@ use injective_cosmwasm::{
 use injective_math::FPDecimal;
 
 use crate::{
+    fees::{accrue_fees, accrue_management_fee, mint_lp_msg},
+    helpers::{cw20_to_bank_msg, transfer_msg},
+    keeper::{check_cooldown, check_keeper},
+    ledger::{check_idle, record_order, start_operation},
     msg::{CallbackMsg, ExecuteMsg},
-    querier::{query_balance, query_decimals},
-    query::{basket_value_usdt, get_basket_ideal_ratio},
-    reply::ATOMIC_ORDER_REPLY_ID,
-    state::{ClobCache, BASKET, CLOB_CACHE, CONFIG},
+    oracle::{check_feeds, check_oracle_deviation},
+    orderbook::{depth_limited_quantity, is_buy},
+    passive::passive_rebalance,
+    querier::{query_decimals, query_holdings, query_token_info},
+    query::{
+        asset_weights, basket_asset_amount_value, basket_asset_limit_price, basket_value_usdt,
+        deposit_holdings, get_basket_ideal_ratio,
+    },
+    reply::order_submsg,
+    slippage::{buy_slippage, check_max_slippage, sell_slippage},
+    state::{
+        Basket, BasketAsset, Config, ConfigUpdate, OperationKind, OrderSide, OwnershipProposal,
+        PlannedSell, RebalanceMode, RebalancePlan, BASKET, CONFIG, OWNERSHIP_PROPOSAL, PAUSE_STATE,
+        REBALANCE_PLAN,
+    },
+    twap::rebalance_step,
+    ContractError,
 };
 
+/// Maximum number of seconds a proposed owner has to claim the ownership.
+pub const MAX_PROPOSAL_TTL: u64 = 1_209_600;
+
 pub fn update_config(
     deps: DepsMut<InjectiveQueryWrapper>,
-    _info: MessageInfo,
-    _new_owner: Option<Addr>,
-    _new_dens_addr: Option<Addr>,
-) -> Result<Response<InjectiveMsgWrapper>, StdError> {
-    let _config = CONFIG.update(deps.storage, |config| -> Result<_, StdError> { Ok(config) })?;
+    env: Env,
+    msg: ConfigUpdate,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+
+    if let Some(pyth_contract_addr) = msg.pyth_contract_addr {
+        config.pyth_contract_addr = pyth_contract_addr;
+    }
+
+    if let Some(deposit_asset) = msg.deposit_asset {
+        config.deposit_asset = deposit_asset;
+    }
+
+    if let Some(cw20_adapter) = msg.cw20_adapter {
+        config.cw20_adapter = Some(cw20_adapter);
+    }
+
+    if let Some(guardian) = msg.guardian {
+        config.guardian = Some(guardian);
+    }
+
+    if msg.clear_guardian {
+        config.guardian = None;
+    }
+
+    if let Some(timelock_delay) = msg.timelock_delay {
+        config.timelock_delay = timelock_delay;
+    }
+
+    if let Some(fees) = msg.fees {
+        // Settle the management fee at the previous rate
+        let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+        let (_, fee_messages) = accrue_management_fee(deps.storage, &env, &config, total_supply)?;
+        messages.extend(fee_messages);
+
+        config.fees = fees;
+    }
+
+    if let Some(slippage) = msg.slippage {
+        config.slippage = slippage;
+    }
+
+    if let Some(keeper) = msg.keeper {
+        config.keeper = keeper;
+    }
+
+    if let Some(twap) = msg.twap {
+        config.twap = Some(twap);
+    }
+
+    if let Some(rebalance_mode) = msg.rebalance_mode {
+        config.rebalance_mode = rebalance_mode;
+    }
+
+    if let Some(order_failure) = msg.order_failure {
+        config.order_failure = order_failure;
+    }
+
+    if let Some(oracle) = msg.oracle {
+        config.oracle = oracle;
+    }
+
+    if let Some(pricing) = msg.pricing {
+        config.pricing = pricing;
+    }
+
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_config")
+        .add_messages(messages))
+}
+
+pub fn propose_new_owner(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    expires_in: u64,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let new_owner = deps.api.addr_validate(&owner)?;
+
+    if new_owner == config.owner {
+        return Err(StdError::generic_err("New owner cannot be the current owner").into());
+    }
+
+    if expires_in > MAX_PROPOSAL_TTL {
+        return Err(StdError::generic_err(format!(
+            "Parameter expires_in cannot be higher than {}",
+            MAX_PROPOSAL_TTL
+        ))
+        .into());
+    }
+
+    OWNERSHIP_PROPOSAL.save(
+        deps.storage,
+        &OwnershipProposal {
+            owner: new_owner.to_owned(),
+            ttl: env.block.time.seconds() + expires_in,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "propose_new_owner"),
+        ("new_owner", new_owner.as_str()),
+    ]))
+}
+
+pub fn drop_ownership_proposal(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    info: MessageInfo,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("action", "drop_ownership_proposal"))
+}
+
+pub fn claim_ownership(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let proposal = OWNERSHIP_PROPOSAL.load(deps.storage)?;
+
+    if info.sender != proposal.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if env.block.time.seconds() > proposal.ttl {
+        return Err(ContractError::OwnershipProposalExpired {});
+    }
+
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    CONFIG.update(deps.storage, |mut config| -> StdResult<_> {
+        config.owner = proposal.owner.to_owned();
+        Ok(config)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "claim_ownership"),
+        ("new_owner", proposal.owner.as_str()),
+    ]))
+}
+
+pub fn set_pause(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    info: MessageInfo,
+    deposit: Option<bool>,
+    withdraw: Option<bool>,
+    rebalance: Option<bool>,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    let is_owner = info.sender == config.owner;
+    let is_guardian = config.guardian.as_ref() == Some(&info.sender);
+
+    if !is_owner && !is_guardian {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // The guardian can only pause, unpausing is left to the owner
+    if !is_owner && [deposit, withdraw, rebalance].contains(&Some(false)) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut pause_state = PAUSE_STATE.may_load(deps.storage)?.unwrap_or_default();
+    pause_state.deposit = deposit.unwrap_or(pause_state.deposit);
+    pause_state.withdraw = withdraw.unwrap_or(pause_state.withdraw);
+    pause_state.rebalance = rebalance.unwrap_or(pause_state.rebalance);
+
+    PAUSE_STATE.save(deps.storage, &pause_state)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "set_pause".to_string()),
+        ("deposit", pause_state.deposit.to_string()),
+        ("withdraw", pause_state.withdraw.to_string()),
+        ("rebalance", pause_state.rebalance.to_string()),
+    ]))
+}
+
+pub fn update_asset_weight(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    asset: AssetInfo,
+    weight: Uint128,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let mut basket = BASKET.load(deps.storage)?;
+    let basket_asset = basket
+        .assets
+        .iter_mut()
+        .find(|b| b.asset.info == asset)
+        .ok_or_else(|| StdError::not_found(format!("Basket asset {}", asset)))?;
+
+    basket_asset.weight = weight;
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_asset_weight".to_string()),
+        ("asset", asset.to_string()),
+        ("weight", weight.to_string()),
+    ]))
+}
+
+pub fn add_asset(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    basket_asset: BasketAsset,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let mut basket = BASKET.load(deps.storage)?;
+
+    if basket
+        .assets
+        .iter()
+        .any(|b| b.asset.info == basket_asset.asset.info)
+    {
+        return Err(StdError::generic_err(format!(
+            "Basket asset {} already exists",
+            basket_asset.asset.info
+        ))
+        .into());
+    }
+
+    let asset = basket_asset.asset.info.to_string();
+    basket.assets.push(basket_asset);
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new().add_attributes(vec![("action", "add_asset"), ("asset", &asset)]))
+}
+
+pub fn retire_asset(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    asset: AssetInfo,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let mut basket = BASKET.load(deps.storage)?;
 
-    Ok(Response::new().add_attribute("action", "update_config"))
+    if !basket
+        .assets
+        .iter()
+        .any(|b| b.asset.info != asset && !b.weight.is_zero())
+    {
+        return Err(StdError::generic_err("Cannot retire the last active basket asset").into());
+    }
+
+    let basket_asset = basket
+        .assets
+        .iter_mut()
+        .find(|b| b.asset.info == asset)
+        .ok_or_else(|| StdError::not_found(format!("Basket asset {}", asset)))?;
+
+    basket_asset.weight = Uint128::zero();
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "retire_asset".to_string()),
+        ("asset", asset.to_string()),
+    ]))
+}
+
+pub fn update_drift_band(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    asset: Option<AssetInfo>,
+    drift_band_bps: u64,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let mut basket = BASKET.load(deps.storage)?;
+
+    match &asset {
+        Some(asset) => {
+            let basket_asset = basket
+                .assets
+                .iter_mut()
+                .find(|b| &b.asset.info == asset)
+                .ok_or_else(|| StdError::not_found(format!("Basket asset {}", asset)))?;
+
+            basket_asset.drift_band_bps = Some(drift_band_bps);
+        }
+        None => basket.drift_band_bps = drift_band_bps,
+    }
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_drift_band".to_string()),
+        (
+            "asset",
+            asset.map_or_else(|| "basket".to_string(), |asset| asset.to_string()),
+        ),
+        ("drift_band_bps", drift_band_bps.to_string()),
+    ]))
+}
+
+/// Market sell of a basket asset for the rebalance, shrunk to what the orderbook can fill within
+/// the asset price impact limit. The rest is left to the next rebalances.
+#[allow(clippy::too_many_arguments)]
+pub fn rebalance_sell_order(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    config: &Config,
+    basket_asset: &BasketAsset,
+    market: &SpotMarket,
+    price: Decimal,
+    quantity: Decimal,
+    decimals: u64,
+    subaccount_id: &SubaccountId,
+    contract: &Addr,
+) -> StdResult<Option<CosmosMsg<InjectiveMsgWrapper>>> {
+    let slippage = basket_asset.slippage(&config.slippage);
+    let usdt_decimals = 6u64;
+    let mut quantity = quantity;
+
+    if let Some(max_price_impact_bps) = slippage.max_price_impact_bps {
+        quantity = depth_limited_quantity(
+            querier,
+            market,
+            &OrderType::SellAtomic,
+            quantity,
+            decimals,
+            max_price_impact_bps,
+        )?;
+
+        if quantity.is_zero() {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(spot_order(
+        sell_slippage(slippage.rebalance_bps)?,
+        price,
+        quantity,
+        market,
+        decimals,
+        usdt_decimals,
+        OrderType::SellAtomic,
+        subaccount_id,
+        contract,
+    )?))
 }
 
 pub fn sell_inj_spot_order(
@@ -82,13 +444,42 @@ pub fn spot_order(
     subaccount_id: &SubaccountId,
     sender: &Addr,
 ) -> StdResult<CosmosMsg<InjectiveMsgWrapper>> {
+    let order = spot_order_data(
+        slippage,
+        price,
+        quantity,
+        market,
+        base_decimals,
+        quote_decimals,
+        order_type,
+        subaccount_id,
+        sender,
+    )?;
+
+    Ok(create_spot_market_order_msg(sender.to_owned(), order))
+}
+
+/// Order at `slippage` times the oracle price, rounded to the market ticks. The quantity of a
+/// buy above the oracle price is reduced so that it costs the same.
+#[allow(clippy::too_many_arguments)]
+pub fn spot_order_data(
+    slippage: Decimal,
+    price: Decimal,
+    quantity: Decimal,
+    market: &SpotMarket,
+    base_decimals: u64,
+    quote_decimals: u64,
+    order_type: OrderType,
+    subaccount_id: &SubaccountId,
+    sender: &Addr,
+) -> StdResult<SpotOrder> {
     let price_s = price.checked_mul(slippage)?;
 
     let price_scale_factor = FPDecimal::from(10_i128.pow((base_decimals - quote_decimals) as u32));
     let mut price_fp = FPDecimal::from_str(&price_s.to_string())?.div(price_scale_factor);
     price_fp = market.min_price_tick_size * (price_fp.div(market.min_price_tick_size)).int();
 
-    let quantity_s = if slippage.gt(&Decimal::one()) {
+    let quantity_s = if is_buy(&order_type) && slippage.gt(&Decimal::one()) {
         quantity
             .checked_div(slippage)
             .map_err(|e| StdError::GenericErr { msg: e.to_string() })?
@@ -101,16 +492,14 @@ pub fn spot_order(
     quantity_fp =
         market.min_quantity_tick_size * (quantity_fp.div(market.min_quantity_tick_size)).int();
 
-    let order = SpotOrder::new(
+    Ok(SpotOrder::new(
         price_fp,
         quantity_fp,
         order_type,
         &market.market_id,
         subaccount_id.to_owned(),
         Some(sender.to_owned()),
-    );
-
-    Ok(create_spot_market_order_msg(sender.to_owned(), order))
+    ))
 }
 
 pub fn deposit(
@@ -118,35 +507,101 @@ pub fn deposit(
     env: Env,
     info: MessageInfo,
     asset: Asset,
-) -> Result<Response<InjectiveMsgWrapper>, StdError> {
+    min_lp_out: Option<Uint128>,
+    deadline: Option<Timestamp>,
+    max_slippage_bps: Option<u64>,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     let config = CONFIG.load(deps.storage)?;
 
-    if let AssetInfo::NativeToken { denom } = &config.deposit_asset {
-        if let Some(other_coin) = info.funds.iter().find(|x| x.denom != *denom) {
-            return Err(StdError::generic_err(format!(
-                "Deposit other tokens {}",
-                other_coin
-            )));
+    match &config.deposit_asset {
+        AssetInfo::NativeToken { denom } => {
+            if let Some(other_coin) = info.funds.iter().find(|x| x.denom != *denom) {
+                return Err(
+                    StdError::generic_err(format!("Deposit other tokens {}", other_coin)).into(),
+                );
+            }
+        }
+        AssetInfo::Token { .. } => {
+            return Err(StdError::generic_err("Tokens are deposited with cw20 Send").into());
         }
     }
 
     asset.assert_sent_native_token_balance(&info)?;
 
-    CLOB_CACHE.save(deps.storage, &vec![ClobCache::new()])?;
+    deposit_into_basket(
+        deps,
+        env,
+        info.sender,
+        asset,
+        min_lp_out,
+        deadline,
+        max_slippage_bps,
+    )
+}
+
+/// Buys the basket assets with a deposit already received by the contract.
+pub fn deposit_into_basket(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    sender: Addr,
+    asset: Asset,
+    min_lp_out: Option<Uint128>,
+    deadline: Option<Timestamp>,
+    max_slippage_bps: Option<u64>,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if let Some(deadline) = deadline {
+        if env.block.time > deadline {
+            return Err(ContractError::DeadlineExpired {
+                deadline: deadline.seconds(),
+            });
+        }
+    }
+
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .deposit
+    {
+        return Err(ContractError::DepositPaused {});
+    }
+
+    if asset.info != config.deposit_asset {
+        return Err(StdError::generic_err(format!("Deposit other tokens {}", asset.info)).into());
+    }
 
     let basket = BASKET.load(deps.storage)?;
+    check_feeds(&deps.querier, &env, &config, &basket)?;
+
+    // Minted before `after_deposit` prices the new LP tokens, so depositors don't pay for it
+    let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+    let (_, fee_messages) = accrue_management_fee(deps.storage, &env, &config, total_supply)?;
+
+    let operation_id = start_operation(deps.storage, &env, OperationKind::Deposit, &sender)?;
 
     let contract = &env.contract.address;
+    let pricing = config.pricing.pricing(OperationKind::Deposit);
 
-    let asset_ideals = get_basket_ideal_ratio(deps.as_ref(), &env)?;
+    // The entry fee is kept out of the orders and paid to the collector by `after_deposit`
+    let invested = asset
+        .amount
+        .checked_sub(config.fees.entry_fee(&sender, asset.amount))?;
+
+    let asset_ideals = get_basket_ideal_ratio(deps.as_ref(), &env, pricing)?;
 
     let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
-    let slippage = Decimal::from_ratio(5u128, 100u128).checked_add(Decimal::one())?;
     let mut submessages: Vec<SubMsg<InjectiveMsgWrapper>> = vec![];
     let injective_querier = InjectiveQuerier::new(&deps.querier);
 
     let mut log: Vec<String> = vec![];
-    for asset_ideal in asset_ideals {
+    let mut events: Vec<Event> = vec![];
+    for (index, asset_ideal) in asset_ideals.into_iter().enumerate() {
+        // Retired assets are not bought anymore
+        if asset_ideal.ratio.is_zero() {
+            continue;
+        }
+
         let market =
             injective_querier.query_spot_market(&asset_ideal.basket_asset.spot_market_id)?;
         if let Some(market) = market.market {
@@ -162,14 +617,59 @@ pub fn deposit(
             log.push(format!("base_decimals {:?}", base_decimals));
             log.push(format!("asset_ideal {:?}", asset_ideal));
 
+            let slippage = asset_ideal.basket_asset.slippage(&config.slippage);
+            check_max_slippage(max_slippage_bps, slippage.deposit_bps)?;
+            let slippage_bps = slippage
+                .deposit_bps
+                .min(max_slippage_bps.unwrap_or(u64::MAX));
+
+            let mut quantity = asset_ideal.ratio.checked_mul(
+                Decimal::from_atomics(invested, quote_decimals as u32)
+                    .map_err(|e| StdError::generic_err(e.to_string()))?,
+                // .checked_mul(Decimal::from_str("0.998")?)?,
+            )?;
+
+            // The part of the deposit which is not spent is refunded by `after_deposit`
+            if let Some(max_price_impact_bps) = slippage.max_price_impact_bps {
+                quantity = depth_limited_quantity(
+                    &deps.querier,
+                    &market,
+                    &OrderType::BuyAtomic,
+                    quantity,
+                    base_decimals,
+                    max_price_impact_bps,
+                )?;
+                log.push(format!("depth_limited_quantity {:?}", quantity));
+
+                if quantity.is_zero() {
+                    continue;
+                }
+            }
+
+            if let Some(event) = check_oracle_deviation(
+                &deps.querier,
+                &env,
+                &config,
+                &asset_ideal.basket_asset,
+                &market,
+                base_decimals,
+            )? {
+                events.push(event);
+            }
+
+            let limit_price = basket_asset_limit_price(
+                &deps.querier,
+                &env,
+                &config,
+                &asset_ideal.basket_asset,
+                OperationKind::Deposit,
+                OrderSide::Buy,
+            )?;
+
             let order_msg = spot_order(
-                slippage,
-                asset_ideal.price,
-                asset_ideal.ratio.checked_mul(
-                    Decimal::from_atomics(asset.amount, quote_decimals as u32)
-                        .map_err(|e| StdError::generic_err(e.to_string()))?,
-                    // .checked_mul(Decimal::from_str("0.998")?)?,
-                )?,
+                buy_slippage(slippage_bps)?,
+                limit_price,
+                quantity,
                 &market,
                 base_decimals,
                 quote_decimals,
@@ -180,70 +680,286 @@ pub fn deposit(
 
             log.push(format!("order_msg {:?}", order_msg));
 
-            let order_message = SubMsg::reply_on_success(order_msg, ATOMIC_ORDER_REPLY_ID);
-            submessages.push(order_message);
+            record_order(
+                deps.storage,
+                &asset_ideal.basket_asset.asset.info,
+                &market.market_id,
+                OrderSide::Buy,
+            )?;
+            submessages.push(order_submsg(order_msg, index));
         }
     }
 
-    let basket_value_in_usdt = basket_value_usdt(&deps.querier, &env, &config, &basket)?;
+    // The deposit was received before this message executes
+    let basket_value_in_usdt = basket_value_usdt(&deps.querier, &env, &config, &basket, pricing)?
+        .checked_sub(asset.amount)?;
 
+    // The orders are paid in the bank denom of a cw20 deposit asset
     let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    if let AssetInfo::Token { contract_addr } = &asset.info {
+        messages.push(cw20_to_bank_msg(&config, contract_addr, asset.amount)?);
+    }
+    messages.extend(fee_messages);
+
     let after_deposit_msg = CosmosMsg::<InjectiveMsgWrapper>::Wasm(WasmMsg::Execute {
         contract_addr: contract.to_owned().into_string(),
         msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::AfterDeposit {
             deposit: asset.amount,
-            sender: info.sender.to_owned(),
+            sender: sender.to_owned(),
             basket_value: basket_value_in_usdt,
+            min_lp_out,
         }))?,
         funds: vec![],
     });
 
-    if info.sender != env.contract.address {
-        messages.push(after_deposit_msg);
-    }
-
     Ok(Response::new()
         .add_attribute("action", "deposit")
+        .add_attribute("operation_id", operation_id.to_string())
         .add_attribute("log", format!("{:?}", log))
+        .add_events(events)
+        .add_messages(messages)
         .add_submessages(submessages)
+        .add_message(after_deposit_msg))
+}
+
+/// Mints LP tokens for basket assets delivered in proportion to the current holdings, without
+/// placing any orders. Coins sent above that proportion are refunded.
+pub fn deposit_in_kind(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .deposit
+    {
+        return Err(ContractError::DepositPaused {});
+    }
+
+    check_idle(deps.storage)?;
+
+    let basket = BASKET.load(deps.storage)?;
+
+    if let Some(other_coin) = info.funds.iter().find(|coin| {
+        !basket.assets.iter().any(|basket_asset| {
+            basket_asset.asset.info
+                == AssetInfo::NativeToken {
+                    denom: coin.denom.to_owned(),
+                }
+        })
+    }) {
+        return Err(StdError::generic_err(format!("Deposit other tokens {}", other_coin)).into());
+    }
+
+    check_feeds(&deps.querier, &env, &config, &basket)?;
+
+    let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+    if total_supply.is_zero() {
+        return Err(StdError::generic_err("Cannot deposit in kind into an empty basket").into());
+    }
+
+    // Holdings before the deposit and amount sent, for each basket asset
+    let mut holdings: Vec<(&BasketAsset, Uint128, Uint128)> = vec![];
+    for basket_asset in basket.assets.iter() {
+        let sent = match &basket_asset.asset.info {
+            AssetInfo::NativeToken { denom } => info
+                .funds
+                .iter()
+                .filter(|coin| coin.denom == *denom)
+                .map(|coin| coin.amount)
+                .sum(),
+            AssetInfo::Token { .. } => Uint128::zero(),
+        };
+        let balance = query_holdings(
+            &deps.querier,
+            &basket_asset.asset.info,
+            &env.contract.address,
+        )?;
+
+        holdings.push((basket_asset, balance.checked_sub(sent)?, sent));
+    }
+
+    // Retired assets and assets the basket doesn't hold yet are not accepted
+    let is_accepted = |basket_asset: &BasketAsset, held: Uint128| {
+        !basket_asset.weight.is_zero() && !held.is_zero()
+    };
+
+    // The asset sent in the smallest proportion of its holdings bounds the deposit
+    let deposit_ratio = holdings
+        .iter()
+        .filter(|(basket_asset, held, _)| is_accepted(basket_asset, *held))
+        .map(|(_, held, sent)| Decimal::from_ratio(*sent, *held))
+        .min()
+        .unwrap_or_default();
+
+    if deposit_ratio.is_zero() {
+        return Err(StdError::generic_err("Missing basket assets for an in-kind deposit").into());
+    }
+
+    let pricing = config.pricing.pricing(OperationKind::Deposit);
+    let usdt_decimals = 6u32;
+    let deposit_balance = deposit_holdings(&deps.querier, &config, &env.contract.address)?;
+    let mut basket_value_before_deposit = Decimal::from_atomics(deposit_balance, usdt_decimals)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let mut deposit_value = Decimal::zero();
+    let mut transfers: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    let mut entry_fees: Vec<String> = vec![];
+
+    for (basket_asset, held, sent) in holdings {
+        basket_value_before_deposit = basket_value_before_deposit.checked_add(
+            basket_asset_amount_value(&deps.querier, &env, &config, basket_asset, held, pricing)?,
+        )?;
+
+        let accepted = if is_accepted(basket_asset, held) {
+            (held * deposit_ratio).min(sent)
+        } else {
+            Uint128::zero()
+        };
+
+        // The entry fee is taken in kind, the LP tokens are minted on the rest
+        let entry_fee = config.fees.entry_fee(&info.sender, accepted);
+        if !entry_fee.is_zero() {
+            transfers.push(transfer_msg(
+                &basket_asset.asset.info,
+                &config.fees.collector,
+                entry_fee,
+            )?);
+            entry_fees.push(format!("{}{}", entry_fee, basket_asset.asset.info));
+        }
+
+        let invested = accepted.checked_sub(entry_fee)?;
+        if !invested.is_zero() {
+            deposit_value = deposit_value.checked_add(basket_asset_amount_value(
+                &deps.querier,
+                &env,
+                &config,
+                basket_asset,
+                invested,
+                pricing,
+            )?)?;
+        }
+
+        let refund = sent.checked_sub(accepted)?;
+        if !refund.is_zero() {
+            transfers.push(transfer_msg(
+                &basket_asset.asset.info,
+                &info.sender,
+                refund,
+            )?);
+        }
+    }
+
+    let (management_fee, mut messages) =
+        accrue_management_fee(deps.storage, &env, &config, total_supply)?;
+    let total_supply = total_supply.checked_add(management_fee)?;
+
+    let lp_amount = total_supply
+        * deposit_value
+            .checked_div(basket_value_before_deposit)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+
+    messages.push(mint_lp_msg(&config, &info.sender, lp_amount)?);
+    messages.extend(transfers);
+
+    Ok(Response::new()
+        .add_attributes(vec![
+            ("action", "deposit_in_kind".to_string()),
+            ("deposit_ratio", deposit_ratio.to_string()),
+            ("deposit_value", deposit_value.to_string()),
+            ("entry_fees", entry_fees.join(",")),
+            ("lp_amount", lp_amount.to_string()),
+        ])
         .add_messages(messages))
 }
 
 pub fn rebalance(
     deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
-    _info: MessageInfo,
-) -> Result<Response<InjectiveMsgWrapper>, StdError> {
+    info: MessageInfo,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     let config = CONFIG.load(deps.storage)?;
 
-    CLOB_CACHE.save(deps.storage, &vec![ClobCache::new()])?;
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .rebalance
+    {
+        return Err(ContractError::RebalancePaused {});
+    }
+
+    check_keeper(&config, &info.sender)?;
+
+    // Nothing else runs while a rebalance waits for its callbacks
+    check_idle(deps.storage)?;
+
+    check_feeds(&deps.querier, &env, &config, &BASKET.load(deps.storage)?)?;
+
+    if let Some(plan) = REBALANCE_PLAN.may_load(deps.storage)? {
+        return rebalance_step(deps, env, info, config, plan);
+    }
+
+    check_cooldown(deps.storage, &env, &config)?;
+
+    if let RebalanceMode::Passive { offset_bps } = config.rebalance_mode {
+        return passive_rebalance(deps, env, info, config, offset_bps);
+    }
 
     let basket = BASKET.load(deps.storage)?;
+    let pricing = config.pricing.pricing(OperationKind::Rebalance);
+    let basket_value = basket_value_usdt(&deps.querier, &env, &config, &basket, pricing)?;
+
+    let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+    let fee_messages = accrue_fees(deps.storage, &env, &config, basket_value, total_supply)?;
 
     let contract = &env.contract.address;
 
-    let asset_ideals = get_basket_ideal_ratio(deps.as_ref(), &env)?;
+    let asset_ideals = get_basket_ideal_ratio(deps.as_ref(), &env, pricing)?;
+    let weights = asset_weights(&deps.querier, &env, &config, &basket, pricing)?;
+
+    if weights.iter().all(|w| w.within_band) {
+        return Err(ContractError::WithinDriftBands {});
+    }
+
+    let operation_id = start_operation(deps.storage, &env, OperationKind::Rebalance, &info.sender)?;
 
     let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
-    let slippage = Decimal::from_ratio(15u128, 100u128).checked_add(Decimal::one())?;
     let mut submessages: Vec<SubMsg<InjectiveMsgWrapper>> = vec![];
     let injective_querier = InjectiveQuerier::new(&deps.querier);
 
-    let basket_value = basket_value_usdt(&deps.querier, &env, &config, &basket)?;
     let usdt_decimals = 6u64;
 
     let mut log: Vec<String> = vec![];
+    let mut events: Vec<Event> = vec![];
+    let mut retired_assets: Vec<AssetInfo> = vec![];
+    let mut planned_sells: Vec<PlannedSell> = vec![];
 
-    for asset_ideal in asset_ideals {
+    for (index, (asset_ideal, weight)) in asset_ideals.into_iter().zip(weights).enumerate() {
         let market =
             injective_querier.query_spot_market(&asset_ideal.basket_asset.spot_market_id)?;
 
         if let Some(market) = market.market {
-            let amount = query_balance(
+            let amount = query_holdings(
                 &deps.querier,
                 &asset_ideal.basket_asset.asset.info,
                 &env.contract.address,
             )?;
+
+            // Retired asset which is fully liquidated (up to the unsellable dust)
+            if asset_ideal.basket_asset.weight.is_zero()
+                && FPDecimal::from(amount) < market.min_quantity_tick_size
+            {
+                retired_assets.push(asset_ideal.basket_asset.asset.info.to_owned());
+                continue;
+            }
+
+            if weight.within_band {
+                log.push(format!("within_band {}", weight.asset));
+                continue;
+            }
+
             let decimals = query_decimals(&deps.querier, &asset_ideal.basket_asset.asset.info);
             let current_quantity = Decimal::from_atomics(amount, decimals as u32).unwrap();
 
@@ -253,36 +969,115 @@ pub fn rebalance(
 
             // less then ==> Sell
             if ideal_quantity.lt(&current_quantity) {
-                let diff = current_quantity.sub(ideal_quantity);
-                let order_msg = spot_order(
-                    slippage,
-                    asset_ideal.price,
-                    diff,
+                let mut diff = current_quantity.sub(ideal_quantity);
+
+                // Sold in equal slices, starting with this one
+                if let Some(twap) = &config.twap {
+                    diff = diff.checked_mul(Decimal::from_ratio(1u64, twap.slices))?;
+                    planned_sells.push(PlannedSell {
+                        asset: asset_ideal.basket_asset.asset.info.to_owned(),
+                        quantity: diff,
+                    });
+                }
+
+                if let Some(event) = check_oracle_deviation(
+                    &deps.querier,
+                    &env,
+                    &config,
+                    &asset_ideal.basket_asset,
                     &market,
                     decimals,
-                    usdt_decimals,
-                    OrderType::SellAtomic,
-                    &subaccount_id,
-                    contract,
-                )?;
+                )? {
+                    events.push(event);
+                }
 
-                log.push(format!("order_msg {:?}", order_msg));
+                let limit_price = basket_asset_limit_price(
+                    &deps.querier,
+                    &env,
+                    &config,
+                    &asset_ideal.basket_asset,
+                    OperationKind::Rebalance,
+                    OrderSide::Sell,
+                )?;
 
-                let order_message = SubMsg::reply_on_success(order_msg, ATOMIC_ORDER_REPLY_ID);
-                submessages.push(order_message);
+                if let Some(order_msg) = rebalance_sell_order(
+                    &deps.querier,
+                    &config,
+                    &asset_ideal.basket_asset,
+                    &market,
+                    limit_price,
+                    diff,
+                    decimals,
+                    &subaccount_id,
+                    contract,
+                )? {
+                    log.push(format!("order_msg {:?}", order_msg));
+                    record_order(
+                        deps.storage,
+                        &asset_ideal.basket_asset.asset.info,
+                        &market.market_id,
+                        OrderSide::Sell,
+                    )?;
+                    // Indexed in the basket saved without the retired assets
+                    submessages.push(order_submsg(order_msg, index - retired_assets.len()));
+                }
             }
         }
     }
 
+    // Split between the slices of a TWAP rebalance, paid whole when it takes a single one
+    let mut bounty = config.keeper.bounty;
+    if let Some(twap) = &config.twap {
+        if twap.slices > 1 && !planned_sells.is_empty() {
+            bounty = bounty.multiply_ratio(1u64, twap.slices);
+            REBALANCE_PLAN.save(
+                deps.storage,
+                &RebalancePlan {
+                    sells: planned_sells,
+                    slices_left: twap.slices - 1,
+                    slice_interval: twap.slice_interval,
+                    next_slice_at: env.block.time.plus_seconds(twap.slice_interval),
+                    slice_bounty: bounty,
+                },
+            )?;
+        }
+    }
+
+    if !retired_assets.is_empty() {
+        BASKET.save(
+            deps.storage,
+            &Basket {
+                assets: basket
+                    .assets
+                    .into_iter()
+                    .filter(|b| !retired_assets.contains(&b.asset.info))
+                    .collect(),
+                drift_band_bps: basket.drift_band_bps,
+            },
+        )?;
+    }
+
     let after_rebalance_msg = WasmMsg::Execute {
         contract_addr: contract.to_owned().into_string(),
-        msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell {}))?,
+        msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell {
+            basket_value,
+            keeper: info.sender,
+            bounty,
+        }))?,
         funds: vec![],
     };
 
     Ok(Response::new()
         .add_attribute("action", "rebalance")
+        .add_attribute("operation_id", operation_id.to_string())
+        .add_attributes(
+            retired_assets
+                .iter()
+                .map(|asset| ("removed_asset", asset.to_string())),
+        )
         .add_attribute("log", format!("{:?}", log))
+        .add_events(events)
+        .add_messages(fee_messages)
         .add_submessages(submessages)
         .add_message(after_rebalance_msg))
 }
@@ -0,0 +1,438 @@
+use cosmwasm_std::{
+    to_binary, Addr, Api, CosmosMsg, Decimal, Env, StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use injective_cosmwasm::InjectiveMsgWrapper;
+
+use crate::{
+    msg::FeeConfigMsg,
+    state::{Config, FeeConfig, HighWaterMark, HIGH_WATER_MARK, LAST_FEE_ACCRUAL},
+};
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+/// Maximum annual management fee, 10%.
+pub const MAX_MANAGEMENT_FEE_BPS: u64 = 1_000;
+
+/// Maximum performance fee, 50% of the gains.
+pub const MAX_PERFORMANCE_FEE_BPS: u64 = 5_000;
+
+/// Maximum entry and exit fees, 5%.
+pub const MAX_ENTRY_EXIT_FEE_BPS: u64 = 500;
+
+impl FeeConfigMsg {
+    /// Validates the fee rates and addresses into the stored config.
+    pub fn validate(self, api: &dyn Api) -> StdResult<FeeConfig> {
+        if self.management_fee_bps > MAX_MANAGEMENT_FEE_BPS {
+            return Err(StdError::generic_err(format!(
+                "Management fee cannot exceed {} bps",
+                MAX_MANAGEMENT_FEE_BPS
+            )));
+        }
+
+        if self.performance_fee_bps > MAX_PERFORMANCE_FEE_BPS {
+            return Err(StdError::generic_err(format!(
+                "Performance fee cannot exceed {} bps",
+                MAX_PERFORMANCE_FEE_BPS
+            )));
+        }
+
+        if self.entry_fee_bps > MAX_ENTRY_EXIT_FEE_BPS || self.exit_fee_bps > MAX_ENTRY_EXIT_FEE_BPS
+        {
+            return Err(StdError::generic_err(format!(
+                "Entry and exit fees cannot exceed {} bps",
+                MAX_ENTRY_EXIT_FEE_BPS
+            )));
+        }
+
+        Ok(FeeConfig {
+            collector: api.addr_validate(&self.collector)?,
+            management_fee_bps: self.management_fee_bps,
+            performance_fee_bps: self.performance_fee_bps,
+            performance_fee_period: self.performance_fee_period,
+            entry_fee_bps: self.entry_fee_bps,
+            exit_fee_bps: self.exit_fee_bps,
+            fee_exempt: self
+                .fee_exempt
+                .iter()
+                .map(|addr| api.addr_validate(addr))
+                .collect::<StdResult<Vec<Addr>>>()?,
+        })
+    }
+}
+
+impl FeeConfig {
+    /// Part of a deposited `amount` charged as the entry fee.
+    pub fn entry_fee(&self, sender: &Addr, amount: Uint128) -> Uint128 {
+        self.transaction_fee(sender, amount, self.entry_fee_bps)
+    }
+
+    /// Entry fee owed for a deposit of which `invested` went into the basket, so that the fee is
+    /// `entry_fee_bps` of the invested amount plus the fee.
+    pub fn entry_fee_on_invested(&self, sender: &Addr, invested: Uint128) -> Uint128 {
+        if self.fee_exempt.contains(sender) {
+            return Uint128::zero();
+        }
+
+        invested.multiply_ratio(self.entry_fee_bps, BPS_DENOMINATOR - self.entry_fee_bps)
+    }
+
+    /// Part of the `received` deposit asset amount charged as the exit fee.
+    pub fn exit_fee(&self, sender: &Addr, received: Uint128) -> Uint128 {
+        self.transaction_fee(sender, received, self.exit_fee_bps)
+    }
+
+    fn transaction_fee(&self, sender: &Addr, amount: Uint128, fee_bps: u64) -> Uint128 {
+        if self.fee_exempt.contains(sender) {
+            return Uint128::zero();
+        }
+
+        amount.multiply_ratio(fee_bps, BPS_DENOMINATOR)
+    }
+}
+
+/// LP tokens owed to the fee collector for the management fee accrued since the last accrual.
+/// Like the performance fee, the minted tokens dilute holders by the accrued fraction `f` of the
+/// basket, so the collector is minted `supply * f / (1 - f)`.
+pub fn pending_management_fee(
+    storage: &dyn Storage,
+    env: &Env,
+    config: &Config,
+    total_supply: Uint128,
+) -> StdResult<Uint128> {
+    let last_accrual = LAST_FEE_ACCRUAL
+        .may_load(storage)?
+        .unwrap_or(env.block.time);
+    let elapsed = env
+        .block
+        .time
+        .seconds()
+        .saturating_sub(last_accrual.seconds());
+
+    let full = u128::from(BPS_DENOMINATOR * SECONDS_PER_YEAR);
+    // Kept below the whole basket, which decades without an accrual would reach
+    let accrued = (u128::from(config.fees.management_fee_bps) * u128::from(elapsed)).min(full - 1);
+
+    Ok(total_supply.multiply_ratio(accrued, full - accrued))
+}
+
+/// Accrues the management fee and returns the amount of LP tokens minted to the fee collector,
+/// along with the messages minting them.
+pub fn accrue_management_fee(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+    total_supply: Uint128,
+) -> StdResult<(Uint128, Vec<CosmosMsg<InjectiveMsgWrapper>>)> {
+    let fee = pending_management_fee(storage, env, config, total_supply)?;
+
+    LAST_FEE_ACCRUAL.save(storage, &env.block.time)?;
+
+    Ok((fee, mint_fee_msgs(config, fee)?))
+}
+
+/// LP tokens to mint to the fee collector so it owns `performance_fee_bps` of the gains above
+/// the high-water mark, along with the NAV per share once they are minted.
+pub fn performance_fee(
+    basket_value: Uint128,
+    total_supply: Uint128,
+    high_water_mark: Decimal,
+    performance_fee_bps: u64,
+) -> StdResult<(Uint128, Decimal)> {
+    if total_supply.is_zero() {
+        return Ok((Uint128::zero(), high_water_mark));
+    }
+
+    let nav_per_share = Decimal::from_ratio(basket_value, total_supply);
+    if nav_per_share <= high_water_mark {
+        return Ok((Uint128::zero(), high_water_mark));
+    }
+
+    let gains = total_supply * (nav_per_share - high_water_mark);
+    let fee_value = gains.multiply_ratio(performance_fee_bps, BPS_DENOMINATOR);
+
+    // Dilute holders so the minted tokens are worth `fee_value` once minted
+    let fee = total_supply.multiply_ratio(fee_value, basket_value.checked_sub(fee_value)?);
+    let nav_per_share = Decimal::from_ratio(basket_value, total_supply.checked_add(fee)?);
+
+    Ok((fee, nav_per_share))
+}
+
+/// Performance fee the collector would be paid if it was crystallized now, in LP tokens.
+pub fn pending_performance_fee(
+    storage: &dyn Storage,
+    config: &Config,
+    basket_value: Uint128,
+    total_supply: Uint128,
+) -> StdResult<Uint128> {
+    let high_water_mark = match HIGH_WATER_MARK.may_load(storage)? {
+        Some(high_water_mark) => high_water_mark,
+        None => return Ok(Uint128::zero()),
+    };
+
+    let (fee, _) = performance_fee(
+        basket_value,
+        total_supply,
+        high_water_mark.nav_per_share,
+        config.fees.performance_fee_bps,
+    )?;
+
+    Ok(fee)
+}
+
+/// Accrues the management fee, then crystallizes the performance fee on the supply diluted by it.
+pub fn accrue_fees(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+    basket_value: Uint128,
+    total_supply: Uint128,
+) -> StdResult<Vec<CosmosMsg<InjectiveMsgWrapper>>> {
+    let (management_fee, mut messages) = accrue_management_fee(storage, env, config, total_supply)?;
+    let (_, performance_fee_messages) = crystallize_performance_fee(
+        storage,
+        env,
+        config,
+        basket_value,
+        total_supply.checked_add(management_fee)?,
+    )?;
+    messages.extend(performance_fee_messages);
+
+    Ok(messages)
+}
+
+/// Charges the performance fee once `performance_fee_period` has passed since the last
+/// crystallization and raises the high-water mark. Returns the amount of LP tokens minted to
+/// the fee collector, along with the messages minting them.
+pub fn crystallize_performance_fee(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+    basket_value: Uint128,
+    total_supply: Uint128,
+) -> StdResult<(Uint128, Vec<CosmosMsg<InjectiveMsgWrapper>>)> {
+    let high_water_mark = match HIGH_WATER_MARK.may_load(storage)? {
+        Some(high_water_mark) => high_water_mark,
+        None => {
+            // No fee on the gains made before the high-water mark was tracked
+            HIGH_WATER_MARK.save(
+                storage,
+                &HighWaterMark {
+                    nav_per_share: if total_supply.is_zero() {
+                        Decimal::one()
+                    } else {
+                        Decimal::from_ratio(basket_value, total_supply)
+                    },
+                    crystallized_at: env.block.time,
+                },
+            )?;
+            return Ok((Uint128::zero(), vec![]));
+        }
+    };
+
+    if env.block.time
+        < high_water_mark
+            .crystallized_at
+            .plus_seconds(config.fees.performance_fee_period)
+    {
+        return Ok((Uint128::zero(), vec![]));
+    }
+
+    let (fee, nav_per_share) = performance_fee(
+        basket_value,
+        total_supply,
+        high_water_mark.nav_per_share,
+        config.fees.performance_fee_bps,
+    )?;
+
+    // Below the high-water mark, nothing is crystallized
+    if nav_per_share == high_water_mark.nav_per_share {
+        return Ok((Uint128::zero(), vec![]));
+    }
+
+    HIGH_WATER_MARK.save(
+        storage,
+        &HighWaterMark {
+            nav_per_share,
+            crystallized_at: env.block.time,
+        },
+    )?;
+
+    Ok((fee, mint_fee_msgs(config, fee)?))
+}
+
+/// Mints `amount` LP tokens to `recipient`.
+pub fn mint_lp_msg(
+    config: &Config,
+    recipient: &Addr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg<InjectiveMsgWrapper>> {
+    Ok(WasmMsg::Execute {
+        contract_addr: config.lp_token.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Mint {
+            recipient: recipient.to_string(),
+            amount,
+        })?,
+        funds: vec![],
+    }
+    .into())
+}
+
+fn mint_fee_msgs(
+    config: &Config,
+    amount: Uint128,
+) -> StdResult<Vec<CosmosMsg<InjectiveMsgWrapper>>> {
+    if amount.is_zero() {
+        return Ok(vec![]);
+    }
+
+    Ok(vec![mint_lp_msg(config, &config.fees.collector, amount)?])
+}
+
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::{
+        testing::{mock_env, MockStorage},
+        Decimal, Uint128,
+    };
+
+    use super::{
+        accrue_management_fee, crystallize_performance_fee, pending_management_fee,
+        performance_fee, SECONDS_PER_YEAR,
+    };
+    use crate::{
+        state::{Config, FeeConfig, HighWaterMark, HIGH_WATER_MARK, LAST_FEE_ACCRUAL},
+        tests::mock_config,
+    };
+
+    fn config(management_fee_bps: u64, performance_fee_bps: u64) -> Config {
+        Config {
+            fees: FeeConfig {
+                management_fee_bps,
+                performance_fee_bps,
+                performance_fee_period: 100,
+                ..mock_config().fees
+            },
+            ..mock_config()
+        }
+    }
+
+    #[test]
+    fn management_fee_accrues_pro_rata() {
+        let mut storage = MockStorage::new();
+        let mut env = mock_env();
+        let config = config(200, 0);
+        let total_supply = Uint128::new(1_000_000_000);
+
+        LAST_FEE_ACCRUAL
+            .save(&mut storage, &env.block.time)
+            .unwrap();
+
+        // Half a year at 2% per year, 1% of the basket once minted
+        env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR / 2);
+        assert_eq!(
+            pending_management_fee(&storage, &env, &config, total_supply).unwrap(),
+            Uint128::new(10_101_010)
+        );
+
+        let (fee, messages) =
+            accrue_management_fee(&mut storage, &env, &config, total_supply).unwrap();
+        assert_eq!(fee, Uint128::new(10_101_010));
+        assert_eq!(messages.len(), 1);
+
+        // Nothing left to accrue in the same block
+        assert_eq!(
+            pending_management_fee(&storage, &env, &config, total_supply).unwrap(),
+            Uint128::zero()
+        );
+        let (fee, messages) =
+            accrue_management_fee(&mut storage, &env, &config, total_supply).unwrap();
+        assert!(fee.is_zero());
+        assert!(messages.is_empty());
+
+        // Still accruing once the fee left unaccrued exceeds the whole basket
+        let max_fee_config = Config {
+            fees: FeeConfig {
+                management_fee_bps: 1_000,
+                ..config.fees
+            },
+            ..config
+        };
+        env.block.time = env.block.time.plus_seconds(11 * SECONDS_PER_YEAR);
+        assert!(pending_management_fee(&storage, &env, &max_fee_config, total_supply).is_ok());
+    }
+
+    #[test]
+    fn performance_fee_above_high_water_mark() {
+        // 20% of the 200 gained above the high-water mark
+        let (fee, nav_per_share) = performance_fee(
+            Uint128::new(1_200),
+            Uint128::new(1_000),
+            Decimal::one(),
+            2_000,
+        )
+        .unwrap();
+        assert_eq!(fee, Uint128::new(34));
+        assert_eq!(nav_per_share, Decimal::from_ratio(1_200u128, 1_034u128));
+
+        let (fee, nav_per_share) = performance_fee(
+            Uint128::new(900),
+            Uint128::new(1_000),
+            Decimal::one(),
+            2_000,
+        )
+        .unwrap();
+        assert!(fee.is_zero());
+        assert_eq!(nav_per_share, Decimal::one());
+    }
+
+    #[test]
+    fn performance_fee_crystallizes_on_schedule() {
+        let mut storage = MockStorage::new();
+        let mut env = mock_env();
+        let config = config(0, 2_000);
+        let crystallized_at = env.block.time;
+
+        HIGH_WATER_MARK
+            .save(
+                &mut storage,
+                &HighWaterMark {
+                    nav_per_share: Decimal::one(),
+                    crystallized_at,
+                },
+            )
+            .unwrap();
+
+        env.block.time = crystallized_at.plus_seconds(50);
+        let (fee, messages) = crystallize_performance_fee(
+            &mut storage,
+            &env,
+            &config,
+            Uint128::new(1_200),
+            Uint128::new(1_000),
+        )
+        .unwrap();
+        assert!(fee.is_zero());
+        assert!(messages.is_empty());
+
+        env.block.time = crystallized_at.plus_seconds(100);
+        let (fee, messages) = crystallize_performance_fee(
+            &mut storage,
+            &env,
+            &config,
+            Uint128::new(1_200),
+            Uint128::new(1_000),
+        )
+        .unwrap();
+        assert_eq!(fee, Uint128::new(34));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            HIGH_WATER_MARK.load(&storage).unwrap(),
+            HighWaterMark {
+                nav_per_share: Decimal::from_ratio(1_200u128, 1_034u128),
+                crystallized_at: env.block.time,
+            }
+        );
+    }
+}
@@ -1,16 +1,23 @@
-use injective_cosmwasm::InjectiveMsgWrapper;
+use astroport::asset::AssetInfo;
+use cw20::Cw20ExecuteMsg;
+use injective_cosmwasm::{
+    create_batch_update_orders_msg, create_withdraw_msg,
+    get_default_subaccount_id_for_checked_address, InjectiveMsgWrapper, InjectiveQuerier,
+    InjectiveQueryWrapper, MarketId,
+};
 use injective_math::FPDecimal;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_std::{
-    to_binary, Addr, CosmosMsg, CustomQuery, Querier, QuerierWrapper, StdResult, SubMsg, WasmMsg,
-    WasmQuery,
+    to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, CustomQuery, Querier, QuerierWrapper,
+    StdError, StdResult, SubMsg, Uint128, WasmMsg, WasmQuery,
 };
 
 use crate::{
-    msg::{ExecuteMsg, QueryMsg},
-    state::Config,
+    msg::{Cw20AdapterMsg, ExecuteMsg, QueryMsg},
+    querier::query_balance,
+    state::{Basket, Config},
 };
 
 /// CwTemplateContract is a wrapper around Addr that provides a lot of helpers
@@ -54,6 +61,188 @@ pub fn i32_to_dec(source: i32) -> FPDecimal {
     FPDecimal::from(i128::from(source))
 }
 
+/// Sends `amount` of a native coin or a cw20 token from the contract to `recipient`.
+pub fn transfer_msg(
+    asset_info: &AssetInfo,
+    recipient: &Addr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg<InjectiveMsgWrapper>> {
+    Ok(match asset_info {
+        AssetInfo::NativeToken { denom } => BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![Coin::new(amount.u128(), denom)],
+        }
+        .into(),
+        AssetInfo::Token { contract_addr } => WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }
+        .into(),
+    })
+}
+
+/// Bank denom the deposit asset is traded and held in. Spot markets are quoted in bank denoms, so
+/// a cw20 deposit asset is converted by the cw20 adapter into its `factory/{adapter}/{cw20}` denom.
+pub fn quote_denom(deposit_asset: &AssetInfo, cw20_adapter: Option<&Addr>) -> StdResult<String> {
+    match (deposit_asset, cw20_adapter) {
+        (AssetInfo::NativeToken { denom }, _) => Ok(denom.to_owned()),
+        (AssetInfo::Token { contract_addr }, Some(cw20_adapter)) => {
+            Ok(format!("factory/{}/{}", cw20_adapter, contract_addr))
+        }
+        (AssetInfo::Token { contract_addr }, None) => Err(StdError::generic_err(format!(
+            "Cw20 deposit asset {} requires a cw20 adapter",
+            contract_addr
+        ))),
+    }
+}
+
+/// Deposit asset as held by the contract between a deposit and a payout, see `quote_denom`.
+pub fn quote_asset(config: &Config) -> StdResult<AssetInfo> {
+    Ok(AssetInfo::NativeToken {
+        denom: quote_denom(&config.deposit_asset, config.cw20_adapter.as_ref())?,
+    })
+}
+
+/// Converts the cw20 deposit tokens received by the contract into their bank denom.
+pub fn cw20_to_bank_msg(
+    config: &Config,
+    contract_addr: &Addr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg<InjectiveMsgWrapper>> {
+    let cw20_adapter = config.cw20_adapter.as_ref().ok_or_else(|| {
+        StdError::generic_err(format!(
+            "Cw20 deposit asset {} requires a cw20 adapter",
+            contract_addr
+        ))
+    })?;
+
+    Ok(WasmMsg::Execute {
+        contract_addr: contract_addr.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Send {
+            contract: cw20_adapter.to_string(),
+            amount,
+            msg: Binary::default(),
+        })?,
+        funds: vec![],
+    }
+    .into())
+}
+
+/// Pays an amount of the deposit asset, redeeming a cw20 deposit asset from its bank denom.
+pub fn payout_msg(
+    config: &Config,
+    recipient: &Addr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg<InjectiveMsgWrapper>> {
+    match (&config.deposit_asset, &config.cw20_adapter) {
+        (AssetInfo::Token { .. }, Some(cw20_adapter)) => Ok(WasmMsg::Execute {
+            contract_addr: cw20_adapter.to_string(),
+            msg: to_binary(&Cw20AdapterMsg::RedeemAndTransfer {
+                recipient: Some(recipient.to_string()),
+            })?,
+            funds: vec![Coin::new(
+                amount.u128(),
+                quote_denom(&config.deposit_asset, Some(cw20_adapter))?,
+            )],
+        }
+        .into()),
+        _ => transfer_msg(&config.deposit_asset, recipient, amount),
+    }
+}
+
+/// Withdraws from the default subaccount of the contract what its bank balance lacks to pay each
+/// amount. When resting orders hold part of it, the orders of the basket markets are cancelled
+/// first to release their funds.
+pub fn subaccount_withdraw_msgs(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    contract: &Addr,
+    basket: &Basket,
+    payouts: &[(AssetInfo, Uint128)],
+) -> StdResult<Vec<CosmosMsg<InjectiveMsgWrapper>>> {
+    let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
+    let injective_querier = InjectiveQuerier::new(querier);
+
+    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    let mut locked = false;
+    for (asset_info, amount) in payouts {
+        let denom = match asset_info {
+            AssetInfo::NativeToken { denom } => denom,
+            AssetInfo::Token { .. } => continue,
+        };
+
+        let balance = query_balance(querier, asset_info, contract)?;
+        if balance >= *amount {
+            continue;
+        }
+
+        let deposit = injective_querier
+            .query_subaccount_deposit(&subaccount_id, denom)?
+            .deposits;
+        let available: Uint128 = deposit.available_balance.into();
+        let shortfall = (*amount - balance).min(deposit.total_balance.into());
+        if shortfall.is_zero() {
+            continue;
+        }
+
+        locked |= shortfall > available;
+        messages.push(create_withdraw_msg(
+            contract.to_owned(),
+            subaccount_id.to_owned(),
+            Coin::new(shortfall.u128(), denom),
+        ));
+    }
+
+    if locked {
+        messages.insert(
+            0,
+            create_batch_update_orders_msg(
+                contract.to_owned(),
+                Some(subaccount_id),
+                basket
+                    .assets
+                    .iter()
+                    .map(|b| b.spot_market_id.to_owned())
+                    .collect(),
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+            ),
+        );
+    }
+
+    Ok(messages)
+}
+
+/// Checks that a basket asset market is quoted in the deposit asset, which its orders are paid
+/// with.
+pub fn check_quote_denom(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    deposit_asset: &AssetInfo,
+    cw20_adapter: Option<&Addr>,
+    market_id: &MarketId,
+) -> StdResult<()> {
+    let market = InjectiveQuerier::new(querier)
+        .query_spot_market(market_id)?
+        .market
+        .ok_or_else(|| StdError::not_found(format!("Spot market {}", market_id.as_str())))?;
+
+    match quote_denom(deposit_asset, cw20_adapter) {
+        Ok(denom) if denom == market.quote_denom => Ok(()),
+        _ => Err(StdError::generic_err(format!(
+            "Spot market {} is quoted in {} instead of the deposit asset {}",
+            market_id.as_str(),
+            market.quote_denom,
+            deposit_asset
+        ))),
+    }
+}
+
 pub fn get_message_data(
     response: &[SubMsg<InjectiveMsgWrapper>],
     position: usize,
@@ -0,0 +1,56 @@
+use cosmwasm_std::{Addr, Api, Env, StdResult, Storage};
+
+use crate::{
+    msg::KeeperConfigMsg,
+    state::{Config, KeeperConfig, LAST_REBALANCE},
+    ContractError,
+};
+
+impl KeeperConfigMsg {
+    /// Validates the keeper addresses into the stored config.
+    pub fn validate(self, api: &dyn Api) -> StdResult<KeeperConfig> {
+        Ok(KeeperConfig {
+            min_interval: self.min_interval,
+            keepers: self
+                .keepers
+                .iter()
+                .map(|addr| api.addr_validate(addr))
+                .collect::<StdResult<Vec<Addr>>>()?,
+            bounty: self.bounty,
+        })
+    }
+}
+
+/// Checks that `sender` is allowed to rebalance.
+pub fn check_keeper(config: &Config, sender: &Addr) -> Result<(), ContractError> {
+    if !config.keeper.keepers.is_empty()
+        && *sender != config.owner
+        && !config.keeper.keepers.contains(sender)
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(())
+}
+
+/// Checks that the minimum interval since the last rebalance has passed, and records the
+/// rebalance time.
+pub fn check_cooldown(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+) -> Result<(), ContractError> {
+    if let Some(last_rebalance) = LAST_REBALANCE.may_load(storage)? {
+        let next_rebalance = last_rebalance.plus_seconds(config.keeper.min_interval);
+
+        if env.block.time < next_rebalance {
+            return Err(ContractError::RebalanceCooldown {
+                next_rebalance: next_rebalance.seconds(),
+            });
+        }
+    }
+
+    LAST_REBALANCE.save(storage, &env.block.time)?;
+
+    Ok(())
+}
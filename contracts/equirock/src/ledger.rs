@@ -0,0 +1,307 @@
+use astroport::asset::AssetInfo;
+use cosmwasm_std::{Addr, Env, StdError, StdResult, Storage, Uint128};
+use injective_cosmwasm::MarketId;
+use injective_math::FPDecimal;
+
+use crate::{
+    state::{
+        Fill, FillStatus, Operation, OperationKind, OrderSide, OPERATIONS, OPERATION_ID,
+        OPERATION_IN_PROGRESS,
+    },
+    ContractError,
+};
+
+/// Number of operations kept in the ledger.
+pub const MAX_OPERATIONS: u64 = 100;
+
+/// Rejects any operation while another one is waiting for its callback.
+pub fn check_idle(storage: &dyn Storage) -> Result<(), ContractError> {
+    match OPERATION_IN_PROGRESS.may_load(storage)? {
+        Some(id) => Err(ContractError::OperationInProgress { id }),
+        None => Ok(()),
+    }
+}
+
+/// Starts recording a new operation, pruning the oldest one past `MAX_OPERATIONS`. The
+/// operation is in progress until `finish_operation`.
+pub fn start_operation(
+    storage: &mut dyn Storage,
+    env: &Env,
+    kind: OperationKind,
+    initiator: &Addr,
+) -> Result<u64, ContractError> {
+    check_idle(storage)?;
+
+    let id = OPERATION_ID.may_load(storage)?.unwrap_or_default() + 1;
+    OPERATION_ID.save(storage, &id)?;
+
+    OPERATIONS.save(
+        storage,
+        id,
+        &Operation {
+            id,
+            kind,
+            initiator: initiator.to_owned(),
+            started_at: env.block.time,
+            fills: vec![],
+        },
+    )?;
+
+    OPERATION_IN_PROGRESS.save(storage, &id)?;
+
+    if id > MAX_OPERATIONS {
+        OPERATIONS.remove(storage, id - MAX_OPERATIONS);
+    }
+
+    Ok(id)
+}
+
+/// Operation in progress, which must be of the given kind.
+pub fn operation_in_progress(storage: &dyn Storage, kind: OperationKind) -> StdResult<Operation> {
+    let operation = match OPERATION_IN_PROGRESS.may_load(storage)? {
+        Some(id) => OPERATIONS.load(storage, id)?,
+        None => return Err(StdError::generic_err("No operation in progress")),
+    };
+
+    if operation.kind != kind {
+        return Err(StdError::generic_err(format!(
+            "Operation {} is a {:?}, not a {:?}",
+            operation.id, operation.kind, kind
+        )));
+    }
+
+    Ok(operation)
+}
+
+/// Ends the operation in progress, called by its last callback.
+pub fn finish_operation(storage: &mut dyn Storage, kind: OperationKind) -> StdResult<Operation> {
+    let operation = operation_in_progress(storage, kind)?;
+    OPERATION_IN_PROGRESS.remove(storage);
+
+    Ok(operation)
+}
+
+pub fn current_operation(storage: &dyn Storage) -> StdResult<Operation> {
+    let id = OPERATION_ID.load(storage)?;
+    OPERATIONS.load(storage, id)
+}
+
+/// Records an order of the current operation, completed by its reply.
+pub fn record_order(
+    storage: &mut dyn Storage,
+    asset: &AssetInfo,
+    market_id: &MarketId,
+    side: OrderSide,
+) -> StdResult<()> {
+    push_order(storage, asset, market_id, side, FillStatus::Pending)
+}
+
+/// Records a post-only order of the current operation, which gets no reply.
+pub fn record_resting_order(
+    storage: &mut dyn Storage,
+    asset: &AssetInfo,
+    market_id: &MarketId,
+    side: OrderSide,
+) -> StdResult<()> {
+    push_order(storage, asset, market_id, side, FillStatus::Resting)
+}
+
+fn push_order(
+    storage: &mut dyn Storage,
+    asset: &AssetInfo,
+    market_id: &MarketId,
+    side: OrderSide,
+    status: FillStatus,
+) -> StdResult<()> {
+    let mut operation = current_operation(storage)?;
+
+    operation.fills.push(Fill {
+        asset: asset.to_owned(),
+        market_id: market_id.to_owned(),
+        side,
+        quantity: FPDecimal::zero(),
+        price: FPDecimal::zero(),
+        fee: FPDecimal::zero(),
+        status,
+    });
+
+    OPERATIONS.save(storage, operation.id, &operation)
+}
+
+/// Completes the first pending order of `asset` in the current operation.
+pub fn complete_order(
+    storage: &mut dyn Storage,
+    asset: &AssetInfo,
+    quantity: FPDecimal,
+    price: FPDecimal,
+    fee: FPDecimal,
+    status: FillStatus,
+) -> StdResult<Fill> {
+    let mut operation = current_operation(storage)?;
+
+    let fill = operation
+        .fills
+        .iter_mut()
+        .find(|f| f.status == FillStatus::Pending && f.asset == *asset)
+        .ok_or_else(|| StdError::not_found(format!("Pending order of {}", asset)))?;
+    fill.quantity = quantity;
+    fill.price = price;
+    fill.fee = fee;
+    fill.status = status;
+    let fill = fill.to_owned();
+
+    OPERATIONS.save(storage, operation.id, &operation)?;
+
+    Ok(fill)
+}
+
+impl Operation {
+    fn filled(&self, side: OrderSide) -> impl Iterator<Item = &Fill> {
+        self.fills
+            .iter()
+            .filter(move |f| f.side == side && f.status == FillStatus::Filled)
+    }
+
+    /// Deposit asset paid by the filled buy orders, rounded up.
+    pub fn paid(&self) -> StdResult<Uint128> {
+        self.filled(OrderSide::Buy)
+            .try_fold(Uint128::zero(), |acc, f| {
+                acc.checked_add((f.quantity * f.price + f.fee).add(1).into())
+            })
+            .map_err(StdError::from)
+    }
+
+    /// Deposit asset received from the filled sell orders, rounded down.
+    pub fn received(&self) -> StdResult<Uint128> {
+        self.filled(OrderSide::Sell)
+            .try_fold(Uint128::zero(), |acc, f| {
+                acc.checked_add((f.quantity * f.price - f.fee).sub(1).into())
+            })
+            .map_err(StdError::from)
+    }
+
+    pub fn failed_assets(&self) -> Vec<&AssetInfo> {
+        self.fills
+            .iter()
+            .filter(|f| f.status == FillStatus::Failed)
+            .map(|f| &f.asset)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use astroport::asset::AssetInfo;
+    use cosmwasm_std::{
+        testing::{mock_env, MockStorage},
+        Addr, StdError, Uint128,
+    };
+    use injective_cosmwasm::MarketId;
+    use injective_math::FPDecimal;
+
+    use crate::{
+        state::{FillStatus, OperationKind, OrderSide, OPERATIONS},
+        tests::{ATOMUSDT_MARKET_ID, INJUSDT_MARKET_ID},
+    };
+
+    use super::{
+        complete_order, current_operation, finish_operation, record_order, start_operation,
+        MAX_OPERATIONS,
+    };
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_owned(),
+        }
+    }
+
+    #[test]
+    fn fills() {
+        let mut storage = MockStorage::new();
+        let env = mock_env();
+        let inj = native("inj");
+        let atom = native("atom");
+
+        start_operation(
+            &mut storage,
+            &env,
+            OperationKind::Rebalance,
+            &Addr::unchecked("keeper"),
+        )
+        .unwrap();
+        let inj_market = MarketId::new(INJUSDT_MARKET_ID).unwrap();
+        let atom_market = MarketId::new(ATOMUSDT_MARKET_ID).unwrap();
+        record_order(&mut storage, &inj, &inj_market, OrderSide::Sell).unwrap();
+        record_order(&mut storage, &atom, &atom_market, OrderSide::Buy).unwrap();
+        record_order(&mut storage, &atom, &atom_market, OrderSide::Buy).unwrap();
+
+        let fill = complete_order(
+            &mut storage,
+            &inj,
+            FPDecimal::from(2u128),
+            FPDecimal::from(10u128),
+            FPDecimal::one(),
+            FillStatus::Filled,
+        )
+        .unwrap();
+        assert_eq!(fill.market_id, inj_market);
+        assert_eq!(
+            complete_order(
+                &mut storage,
+                &inj,
+                FPDecimal::zero(),
+                FPDecimal::zero(),
+                FPDecimal::zero(),
+                FillStatus::Filled,
+            )
+            .unwrap_err(),
+            StdError::not_found("Pending order of inj")
+        );
+
+        // Completed in the order they were placed
+        complete_order(
+            &mut storage,
+            &atom,
+            FPDecimal::one(),
+            FPDecimal::from(5u128),
+            FPDecimal::zero(),
+            FillStatus::Filled,
+        )
+        .unwrap();
+        complete_order(
+            &mut storage,
+            &atom,
+            FPDecimal::zero(),
+            FPDecimal::zero(),
+            FPDecimal::zero(),
+            FillStatus::Failed,
+        )
+        .unwrap();
+
+        let operation = current_operation(&storage).unwrap();
+        assert_eq!(operation.received().unwrap(), Uint128::new(18));
+        assert_eq!(operation.paid().unwrap(), Uint128::new(6));
+        assert_eq!(operation.failed_assets(), vec![&atom]);
+    }
+
+    #[test]
+    fn prune_operations() {
+        let mut storage = MockStorage::new();
+        let env = mock_env();
+
+        for _ in 0..MAX_OPERATIONS + 2 {
+            start_operation(
+                &mut storage,
+                &env,
+                OperationKind::Deposit,
+                &Addr::unchecked("user"),
+            )
+            .unwrap();
+            finish_operation(&mut storage, OperationKind::Deposit).unwrap();
+        }
+
+        assert!(!OPERATIONS.has(&storage, 2));
+        assert!(OPERATIONS.has(&storage, 3));
+        assert_eq!(current_operation(&storage).unwrap().id, MAX_OPERATIONS + 2);
+    }
+}
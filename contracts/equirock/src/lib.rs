@@ -1,18 +1,28 @@
 pub mod contract;
+pub mod drift;
 mod error;
 pub mod execute;
+pub mod fees;
 pub mod helpers;
+pub mod keeper;
+pub mod ledger;
 // #[cfg(test)]
 // pub mod integration_tests;
 pub mod callback;
 pub mod msg;
+pub mod oracle;
+pub mod orderbook;
+pub mod passive;
 pub mod querier;
 pub mod query;
 pub mod receive;
 pub mod reply;
 pub mod response;
+pub mod slippage;
 pub mod state;
 #[cfg(test)]
 pub mod tests;
+pub mod timelock;
+pub mod twap;
 
 pub use crate::error::ContractError;
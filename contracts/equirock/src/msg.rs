@@ -1,33 +1,176 @@
 use astroport::asset::{Asset, AssetInfo};
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Decimal, Uint128};
+use cosmwasm_std::{Addr, Decimal, Timestamp, Uint128};
 use cw20::Cw20ReceiveMsg;
-use pyth_sdk_cw::Price;
+use pyth_sdk_cw::{Price, PriceIdentifier};
 
-use crate::state::{Basket, BasketAsset, Config};
+use crate::state::{
+    Basket, BasketAsset, Config, HighWaterMark, Operation, OracleConfig, OrderFailurePolicy,
+    PauseState, PendingChange, PricingConfig, RebalanceMode, RebalancePlan, SlippageConfig,
+    TwapConfig,
+};
 
 #[cw_serde]
 pub struct InstantiateMsg {
     pub etf_token_code_id: u64,
     pub etf_token_name: String,
     pub deposit_asset: AssetInfo,
-    pub pyth_contract_addr: Addr,
+    /// Required by a cw20 deposit asset, see `Config`.
+    pub cw20_adapter: Option<String>,
+    pub pyth_contract_addr: String,
     pub basket: Basket,
+    pub timelock_delay: u64,
+    pub guardian: Option<String>,
+    pub fees: FeeConfigMsg,
+    pub slippage: SlippageConfig,
+    pub keeper: KeeperConfigMsg,
+    pub twap: Option<TwapConfig>,
+    pub rebalance_mode: RebalanceMode,
+    pub order_failure: OrderFailurePolicy,
+    pub oracle: OracleConfig,
+    pub pricing: PricingConfig,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    UpdateConfig {},
-    Deposit { asset: Asset },
+    /// Queues a config update. Only the owner can execute it.
+    UpdateConfig(UpdateConfigMsg),
+    /// Proposes a new owner, who has `expires_in` seconds to claim the ownership.
+    ProposeNewOwner {
+        owner: String,
+        expires_in: u64,
+    },
+    /// Removes the pending ownership proposal.
+    DropOwnershipProposal {},
+    /// Accepts the ownership proposal. Only the proposed owner can execute it.
+    ClaimOwnership {},
+    /// Queues a change of the target weight of a basket asset. Only the owner can execute it.
+    UpdateAssetWeight {
+        asset: AssetInfo,
+        weight: Uint128,
+    },
+    /// Queues the addition of a new basket asset. Only the owner can execute it.
+    AddAsset {
+        basket_asset: BasketAsset,
+    },
+    /// Queues the retirement of a basket asset, which zeroes its weight. Its holdings are sold on
+    /// the next rebalance, after which the asset is removed from the basket. Only the owner can
+    /// execute it.
+    RetireAsset {
+        asset: AssetInfo,
+    },
+    /// Queues a change of the drift band of a basket asset, or of the whole basket if `asset` is
+    /// unset. Only the owner can execute it.
+    UpdateDriftBand {
+        asset: Option<AssetInfo>,
+        drift_band_bps: u64,
+    },
+    /// Pauses or unpauses operations. The guardian can only pause, the owner can do both.
+    SetPause {
+        deposit: Option<bool>,
+        withdraw: Option<bool>,
+        rebalance: Option<bool>,
+    },
+    /// Applies a queued change once its timelock has passed. Anyone can execute it.
+    ApplyChange {
+        id: u64,
+    },
+    /// Removes a queued change. Only the owner can execute it.
+    CancelChange {
+        id: u64,
+    },
+    /// Buys the basket assets with the deposit asset. The transaction reverts if fewer than
+    /// `min_lp_out` LP tokens would be minted to the sender, or if it executes after `deadline`.
+    /// `max_slippage_bps` tightens the deposit slippage configured for each asset market.
+    Deposit {
+        asset: Asset,
+        min_lp_out: Option<Uint128>,
+        deadline: Option<Timestamp>,
+        max_slippage_bps: Option<u64>,
+    },
+    /// Deposits the basket assets in proportion to the current holdings instead of buying them.
+    /// Coins sent above that proportion are refunded.
+    DepositInKind {},
+    /// Trades the basket back to its target weights. Limited to the keepers, if any, and the
+    /// owner, at most once per `min_interval`. The caller is paid the keeper bounty.
+    /// With a TWAP config, the first call starts a rebalance plan and sells its first slice, the
+    /// next ones sell the following slices until the plan is complete. The bounty is then split
+    /// between the slices.
+    /// In the passive mode, it replaces the open limit orders of the basket instead.
     Rebalance {},
+    /// Drops the rest of the rebalance plan. Only the owner or the guardian can execute it.
+    AbortRebalance {},
     Callback(CallbackMsg),
     Receive(Cw20ReceiveMsg),
 }
 
+/// Messages of the Injective cw20 adapter, which mints the `factory/{adapter}/{cw20}` bank denom
+/// of the cw20 tokens it is sent and redeems it back.
+#[cw_serde]
+pub enum Cw20AdapterMsg {
+    RedeemAndTransfer { recipient: Option<String> },
+}
+
+/// Fee settings with unvalidated addresses, see `FeeConfig`.
+#[cw_serde]
+pub struct FeeConfigMsg {
+    pub collector: String,
+    pub management_fee_bps: u64,
+    pub performance_fee_bps: u64,
+    pub performance_fee_period: u64,
+    pub entry_fee_bps: u64,
+    pub exit_fee_bps: u64,
+    pub fee_exempt: Vec<String>,
+}
+
+/// Keeper settings with unvalidated addresses, see `KeeperConfig`.
+#[cw_serde]
+pub struct KeeperConfigMsg {
+    pub min_interval: u64,
+    pub keepers: Vec<String>,
+    pub bounty: Uint128,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct UpdateConfigMsg {
+    pub pyth_contract_addr: Option<String>,
+    pub deposit_asset: Option<AssetInfo>,
+    pub cw20_adapter: Option<String>,
+    pub guardian: Option<String>,
+    /// Removes the guardian, cannot be combined with `guardian`.
+    pub clear_guardian: Option<bool>,
+    pub timelock_delay: Option<u64>,
+    pub fees: Option<FeeConfigMsg>,
+    pub slippage: Option<SlippageConfig>,
+    pub keeper: Option<KeeperConfigMsg>,
+    pub twap: Option<TwapConfig>,
+    pub rebalance_mode: Option<RebalanceMode>,
+    pub order_failure: Option<OrderFailurePolicy>,
+    pub oracle: Option<OracleConfig>,
+    pub pricing: Option<PricingConfig>,
+}
+
 #[cw_serde]
 pub enum Cw20HookMsg {
-    /// Withdraws a given amount from the vault.
-    Withdraw {},
+    /// Withdraws a given amount from the vault. The transaction reverts if the sender would
+    /// receive less than `min_received` of the deposit asset, or if it executes after `deadline`.
+    /// `max_slippage_bps` tightens the withdraw slippage configured for each asset market.
+    Withdraw {
+        min_received: Option<Uint128>,
+        deadline: Option<Timestamp>,
+        max_slippage_bps: Option<u64>,
+    },
+    /// Withdraws a given amount from the vault as its share of every basket asset, without
+    /// selling them.
+    WithdrawInKind {},
+    /// Deposits the sent deposit asset token into the vault, with the same bounds as
+    /// `ExecuteMsg::Deposit`.
+    Deposit {
+        min_lp_out: Option<Uint128>,
+        deadline: Option<Timestamp>,
+        max_slippage_bps: Option<u64>,
+    },
 }
 
 #[cw_serde]
@@ -40,6 +183,35 @@ pub enum QueryMsg {
     GetBasketIdealRatio {},
     #[returns(Uint128)]
     GetBasketValueInUsdt {},
+    #[returns(PauseState)]
+    GetPauseState {},
+    #[returns(PendingFeesResponse)]
+    GetPendingFees {},
+    #[returns(Option<HighWaterMark>)]
+    GetHighWaterMark {},
+    /// Share of the basket value held above the target weights, from 0 to 1.
+    #[returns(Decimal)]
+    GetTrackingError {},
+    #[returns(Vec<AssetWeightResponse>)]
+    GetAssetWeights {},
+    #[returns(Option<RebalancePlan>)]
+    GetRebalancePlan {},
+    #[returns(Vec<FeedHealthResponse>)]
+    GetFeedHealth {},
+    #[returns(Vec<PendingChange>)]
+    GetPendingChanges {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Deposit, withdrawal or rebalance with the fills of its orders. Only the last ones are kept.
+    #[returns(Operation)]
+    GetOperation { id: u64 },
+    /// Last operations, most recent first.
+    #[returns(Vec<Operation>)]
+    GetOperations {
+        start_before: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 #[cw_serde]
@@ -49,6 +221,47 @@ pub struct GetBasketAssetIdealRatioResponse {
     pub price: Decimal,
 }
 
+/// Current weight of a basket asset against its target, both as shares of the basket value.
+#[cw_serde]
+pub struct AssetWeightResponse {
+    pub asset: AssetInfo,
+    pub value: Decimal,
+    pub current_weight: Decimal,
+    pub target_weight: Decimal,
+    pub drift_band: Decimal,
+    /// Whether the rebalance leaves the asset alone.
+    pub within_band: bool,
+}
+
+#[cw_serde]
+pub enum FeedStatus {
+    Healthy,
+    /// Older than the maximum age.
+    Stale,
+    /// Confidence interval above the maximum.
+    Uncertain,
+}
+
+/// Pyth price feed of a basket asset against its oracle limits.
+#[cw_serde]
+pub struct FeedHealthResponse {
+    pub asset: AssetInfo,
+    pub price_feed: PriceIdentifier,
+    /// Oldest publish time of the current and EMA prices.
+    pub publish_time: i64,
+    pub conf_bps: u64,
+    pub oracle: OracleConfig,
+    pub status: FeedStatus,
+}
+
+/// Fees accrued but not minted yet, in LP tokens.
+#[cw_serde]
+pub struct PendingFeesResponse {
+    pub management_fee: Uint128,
+    /// Fee on the gains above the high-water mark, if it was crystallized now.
+    pub performance_fee: Uint128,
+}
+
 #[cw_serde]
 pub struct MigrateMsg {}
 
@@ -64,9 +277,17 @@ pub enum CallbackMsg {
         deposit: Uint128,
         sender: Addr,
         basket_value: Uint128,
+        min_lp_out: Option<Uint128>,
     },
     AfterWithdraw {
         sender: Addr,
+        min_received: Option<Uint128>,
+    },
+    /// `bounty` is the part of the keeper bounty paid for this rebalance or slice.
+    AfterRebalanceSell {
+        basket_value: Uint128,
+        keeper: Addr,
+        bounty: Uint128,
     },
-    AfterRebalanceSell {},
+    AfterRebalanceBuy {},
 }
@@ -0,0 +1,400 @@
+use std::str::FromStr;
+
+use cosmwasm_std::{Env, Event, QuerierWrapper, StdError, StdResult, Uint128};
+use injective_cosmwasm::{InjectiveQuerier, InjectiveQueryWrapper, SpotMarket};
+use injective_math::FPDecimal;
+use pyth_sdk_cw::Price;
+
+use crate::{
+    fees::BPS_DENOMINATOR,
+    msg::{FeedHealthResponse, FeedStatus, FetchPriceResponse},
+    querier::query_price_feed,
+    query::{basket_asset_price, pyth_price},
+    state::{
+        Basket, BasketAsset, Config, OperationKind, OracleConfig, OrderSide, PricingConfig,
+        PricingMode,
+    },
+    ContractError,
+};
+
+/// Maximum age of a price, one hour.
+pub const MAX_PRICE_AGE: u64 = 3_600;
+/// Maximum confidence interval of a price, 10% of the price.
+pub const MAX_CONF_BPS: u64 = 1_000;
+
+impl OracleConfig {
+    pub fn validate(&self) -> StdResult<()> {
+        if self.max_age == 0 || self.max_age > MAX_PRICE_AGE {
+            return Err(StdError::generic_err(format!(
+                "Maximum price age must be between 1 and {} seconds",
+                MAX_PRICE_AGE
+            )));
+        }
+
+        if self.max_conf_bps > MAX_CONF_BPS {
+            return Err(StdError::generic_err(format!(
+                "Maximum price confidence cannot exceed {} bps",
+                MAX_CONF_BPS
+            )));
+        }
+
+        if self.max_deviation_bps == Some(0) {
+            return Err(StdError::generic_err(
+                "Maximum oracle deviation must be positive, or unset to skip the check",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl BasketAsset {
+    /// Oracle limits of the asset price feed, falling back to the vault ones.
+    pub fn oracle<'a>(&'a self, default: &'a OracleConfig) -> &'a OracleConfig {
+        self.oracle.as_ref().unwrap_or(default)
+    }
+}
+
+/// Price valuing the basket assets or limiting an order, the conservative mode resolved to one of
+/// its bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pricing {
+    Spot,
+    Ema,
+    /// Highest of the current and EMA prices plus their confidence interval.
+    Upper,
+    /// Lowest of the current and EMA prices minus their confidence interval.
+    Lower,
+}
+
+impl PricingConfig {
+    fn mode(&self, kind: OperationKind) -> PricingMode {
+        match kind {
+            OperationKind::Deposit => self.deposit,
+            OperationKind::Withdraw => self.withdraw,
+            OperationKind::Rebalance => self.rebalance,
+        }
+    }
+
+    /// Pricing valuing the basket in an operation. Deposits mint LP tokens against the basket
+    /// value, which the upper bound keeps from being undervalued. Withdrawals burn them, which the
+    /// lower bound keeps from being overvalued. Rebalances compare the assets with each other,
+    /// which bounds widened by each asset's own confidence would skew, so they use the EMA price.
+    pub fn pricing(&self, kind: OperationKind) -> Pricing {
+        match (self.mode(kind), kind) {
+            (PricingMode::Spot, _) => Pricing::Spot,
+            (PricingMode::Ema, _) | (PricingMode::Conservative, OperationKind::Rebalance) => {
+                Pricing::Ema
+            }
+            (PricingMode::Conservative, OperationKind::Deposit) => Pricing::Upper,
+            (PricingMode::Conservative, OperationKind::Withdraw) => Pricing::Lower,
+        }
+    }
+
+    /// Pricing of the limit price of an order of an operation. The conservative mode uses the
+    /// bound which tightens the limit, the upper one for sells and the lower one for buys.
+    pub fn limit_pricing(&self, kind: OperationKind, side: OrderSide) -> Pricing {
+        match (self.mode(kind), side) {
+            (PricingMode::Spot, _) => Pricing::Spot,
+            (PricingMode::Ema, _) => Pricing::Ema,
+            (PricingMode::Conservative, OrderSide::Sell) => Pricing::Upper,
+            (PricingMode::Conservative, OrderSide::Buy) => Pricing::Lower,
+        }
+    }
+}
+
+impl FetchPriceResponse {
+    pub fn price(&self, pricing: Pricing) -> StdResult<Price> {
+        let current_price = self.current_price;
+        let ema_price = self
+            .ema_price
+            .scale_to_exponent(current_price.expo)
+            .ok_or_else(|| {
+                StdError::generic_err("EMA price cannot be scaled to the current one")
+            })?;
+        let conf = |price: &Price| i64::try_from(price.conf).unwrap_or(i64::MAX);
+
+        Ok(match pricing {
+            Pricing::Spot => current_price,
+            Pricing::Ema => ema_price,
+            Pricing::Upper => Price {
+                price: [current_price, ema_price]
+                    .iter()
+                    .map(|p| p.price.saturating_add(conf(p)))
+                    .max()
+                    .unwrap_or_default(),
+                ..current_price
+            },
+            Pricing::Lower => Price {
+                price: [current_price, ema_price]
+                    .iter()
+                    .map(|p| p.price.saturating_sub(conf(p)).max(0))
+                    .min()
+                    .unwrap_or_default(),
+                ..current_price
+            },
+        })
+    }
+}
+
+/// Confidence interval of a price in basis points of the price, rounded up.
+pub fn conf_bps(price: &Price) -> u64 {
+    if price.price <= 0 {
+        return u64::MAX;
+    }
+
+    let price_value = price.price as u128;
+    let scaled_conf = price.conf as u128 * BPS_DENOMINATOR as u128;
+    let mut conf_bps = scaled_conf / price_value;
+    if conf_bps * price_value < scaled_conf {
+        conf_bps += 1;
+    }
+
+    conf_bps.min(u64::MAX as u128) as u64
+}
+
+/// Health of the price feed of every basket asset.
+pub fn feed_health(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket: &Basket,
+) -> StdResult<Vec<FeedHealthResponse>> {
+    basket
+        .assets
+        .iter()
+        .map(|basket_asset| {
+            let price_feed = query_price_feed(
+                querier,
+                config.pyth_contract_addr.to_owned(),
+                basket_asset.pyth_price_feed,
+            )?
+            .price_feed;
+
+            let current_price = price_feed.get_price_unchecked();
+            let publish_time = current_price
+                .publish_time
+                .min(price_feed.get_ema_price_unchecked().publish_time);
+            let conf_bps = conf_bps(&current_price);
+            let oracle = basket_asset.oracle(&config.oracle).to_owned();
+
+            let status = if env.block.time.seconds() as i64 - publish_time > oracle.max_age as i64 {
+                FeedStatus::Stale
+            } else if conf_bps > oracle.max_conf_bps {
+                FeedStatus::Uncertain
+            } else {
+                FeedStatus::Healthy
+            };
+
+            Ok(FeedHealthResponse {
+                asset: basket_asset.asset.info.to_owned(),
+                price_feed: basket_asset.pyth_price_feed,
+                publish_time,
+                conf_bps,
+                oracle,
+                status,
+            })
+        })
+        .collect()
+}
+
+/// Checks that every basket asset price is recent and certain enough to trade on.
+pub fn check_feeds(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket: &Basket,
+) -> Result<(), ContractError> {
+    for health in feed_health(querier, env, config, basket)? {
+        match health.status {
+            FeedStatus::Healthy => {}
+            FeedStatus::Stale => {
+                return Err(ContractError::StalePrice {
+                    asset: health.asset.to_string(),
+                    publish_time: health.publish_time,
+                })
+            }
+            FeedStatus::Uncertain => {
+                return Err(ContractError::UncertainPrice {
+                    asset: health.asset.to_string(),
+                    conf_bps: health.conf_bps,
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Gap between an oracle price and a market price in basis points of the oracle price, rounded
+/// up.
+pub fn deviation_bps(oracle_price: FPDecimal, market_price: FPDecimal) -> u64 {
+    if oracle_price <= FPDecimal::zero() {
+        return u64::MAX;
+    }
+
+    let gap = if market_price > oracle_price {
+        market_price - oracle_price
+    } else {
+        oracle_price - market_price
+    };
+    let bps = gap * FPDecimal::from(BPS_DENOMINATOR as u128) / oracle_price;
+    let mut deviation_bps: Uint128 = bps.int().into();
+    if FPDecimal::from(deviation_bps) < bps {
+        deviation_bps += Uint128::one();
+    }
+
+    deviation_bps.u128().min(u64::MAX as u128) as u64
+}
+
+/// Compares the current oracle price of a basket asset with the mid price of the market an order
+/// is about to be sent to, whichever price the operation is valued at. Fails past the maximum
+/// deviation, otherwise reports the gap in an event.
+pub fn check_oracle_deviation(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket_asset: &BasketAsset,
+    market: &SpotMarket,
+    base_decimals: u64,
+) -> Result<Option<Event>, ContractError> {
+    let max_deviation_bps = match basket_asset.oracle(&config.oracle).max_deviation_bps {
+        Some(max_deviation_bps) => max_deviation_bps,
+        None => return Ok(None),
+    };
+
+    let price =
+        pyth_price(basket_asset_price(querier, env, config, basket_asset)?.price(Pricing::Spot)?)?;
+
+    let mid_price = InjectiveQuerier::new(querier)
+        .query_spot_market_mid_price_and_tob(&market.market_id)?
+        .mid_price
+        .ok_or_else(|| {
+            StdError::not_found(format!("Mid price of market {}", market.market_id.as_str()))
+        })?;
+
+    // In chain units, like the order prices
+    let quote_decimals = 6u64; // USDT
+    let price_scale_factor = FPDecimal::from(10_i128.pow((base_decimals - quote_decimals) as u32));
+    let oracle_price = FPDecimal::from_str(&price.to_string())? / price_scale_factor;
+
+    let deviation_bps = deviation_bps(oracle_price, mid_price);
+    if deviation_bps > max_deviation_bps {
+        return Err(ContractError::OracleDeviation {
+            asset: basket_asset.asset.info.to_string(),
+            market_id: market.market_id.as_str().to_string(),
+            oracle_price: oracle_price.to_string(),
+            mid_price: mid_price.to_string(),
+            deviation_bps,
+        });
+    }
+
+    Ok(Some(Event::new("oracle_deviation").add_attributes(vec![
+        ("asset", basket_asset.asset.info.to_string()),
+        ("market_id", market.market_id.as_str().to_string()),
+        ("oracle_price", oracle_price.to_string()),
+        ("mid_price", mid_price.to_string()),
+        ("deviation_bps", deviation_bps.to_string()),
+    ])))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use injective_math::FPDecimal;
+    use pyth_sdk_cw::Price;
+
+    use super::{conf_bps, deviation_bps, Pricing};
+    use crate::{
+        msg::FetchPriceResponse,
+        state::{OperationKind, OrderSide, PricingConfig, PricingMode},
+    };
+
+    fn price(price: i64, conf: u64, expo: i32) -> Price {
+        Price {
+            price,
+            conf,
+            expo,
+            publish_time: 0,
+        }
+    }
+
+    #[test]
+    fn confidence() {
+        assert_eq!(conf_bps(&price(1_000, 10, -2)), 100);
+        assert_eq!(conf_bps(&price(3_000, 1, -2)), 4);
+        assert_eq!(conf_bps(&price(0, 1, -2)), u64::MAX);
+    }
+
+    #[test]
+    fn deviation() {
+        let oracle_price = FPDecimal::from_str("9").unwrap();
+
+        assert_eq!(
+            deviation_bps(oracle_price, FPDecimal::from_str("9.009").unwrap()),
+            10
+        );
+        assert_eq!(
+            deviation_bps(oracle_price, FPDecimal::from_str("8.91").unwrap()),
+            100
+        );
+        // Rounded up
+        assert_eq!(
+            deviation_bps(oracle_price, FPDecimal::from_str("9.0001").unwrap()),
+            1
+        );
+        assert_eq!(deviation_bps(FPDecimal::zero(), oracle_price), u64::MAX);
+    }
+
+    #[test]
+    fn pricing() {
+        let config = PricingConfig {
+            deposit: PricingMode::Conservative,
+            withdraw: PricingMode::Conservative,
+            rebalance: PricingMode::Conservative,
+        };
+        assert_eq!(config.pricing(OperationKind::Deposit), Pricing::Upper);
+        assert_eq!(config.pricing(OperationKind::Withdraw), Pricing::Lower);
+        assert_eq!(config.pricing(OperationKind::Rebalance), Pricing::Ema);
+
+        // Limit prices on the protective side of the order
+        assert_eq!(
+            config.limit_pricing(OperationKind::Deposit, OrderSide::Buy),
+            Pricing::Lower
+        );
+        assert_eq!(
+            config.limit_pricing(OperationKind::Withdraw, OrderSide::Sell),
+            Pricing::Upper
+        );
+        assert_eq!(
+            config.limit_pricing(OperationKind::Rebalance, OrderSide::Sell),
+            Pricing::Upper
+        );
+
+        let config = PricingConfig {
+            rebalance: PricingMode::Ema,
+            ..config
+        };
+        assert_eq!(
+            config.limit_pricing(OperationKind::Rebalance, OrderSide::Buy),
+            Pricing::Ema
+        );
+
+        // EMA scaled to the exponent of the current price
+        let fetch_price = FetchPriceResponse {
+            current_price: price(1_000, 10, -2),
+            ema_price: price(9_600, 300, -3),
+        };
+        assert_eq!(fetch_price.price(Pricing::Spot).unwrap().price, 1_000);
+        assert_eq!(fetch_price.price(Pricing::Ema).unwrap(), price(960, 30, -2));
+        assert_eq!(
+            fetch_price.price(Pricing::Upper).unwrap(),
+            price(1_010, 10, -2)
+        );
+        assert_eq!(
+            fetch_price.price(Pricing::Lower).unwrap(),
+            price(930, 10, -2)
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::str::FromStr;
+
+use cosmwasm_std::{CustomQuery, Decimal, QuerierWrapper, QueryRequest, StdError, StdResult};
+use injective_cosmwasm::{InjectiveQueryWrapper, InjectiveRoute, MarketId, OrderType, SpotMarket};
+use injective_math::FPDecimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::fees::BPS_DENOMINATOR;
+
+/// Maximum number of price levels read from one side of the book.
+pub const ORDERBOOK_LEVELS: u64 = 50;
+
+/// Exchange queries missing from `InjectiveQuery`, sent through the same custom query route.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderbookQueryWrapper {
+    pub route: InjectiveRoute,
+    pub query_data: OrderbookQuery,
+}
+
+impl CustomQuery for OrderbookQueryWrapper {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderbookQuery {
+    SpotOrderbook {
+        market_id: MarketId,
+        limit: u64,
+        /// 1 for the buy side, 2 for the sell side.
+        order_side: i32,
+        limit_cumulative_quantity: Option<FPDecimal>,
+        limit_cumulative_notional: Option<FPDecimal>,
+    },
+}
+
+/// Resting quantity at a price, both in chain units.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceLevel {
+    pub p: FPDecimal,
+    pub q: FPDecimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpotOrderbookResponse {
+    pub buys_price_level: Vec<PriceLevel>,
+    pub sells_price_level: Vec<PriceLevel>,
+}
+
+pub fn is_buy(order_type: &OrderType) -> bool {
+    matches!(
+        order_type,
+        OrderType::Buy | OrderType::BuyAtomic | OrderType::BuyPo
+    )
+}
+
+/// Levels an order of `order_type` would fill against, best price first.
+pub fn query_spot_orderbook(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    market_id: &MarketId,
+    order_type: &OrderType,
+    quantity: FPDecimal,
+) -> StdResult<Vec<PriceLevel>> {
+    // Buy orders take the sell side of the book and the other way round
+    let order_side = if is_buy(order_type) { 2 } else { 1 };
+
+    let orderbook_querier: QuerierWrapper<OrderbookQueryWrapper> = QuerierWrapper::new(&**querier);
+    let response: SpotOrderbookResponse =
+        orderbook_querier.query(&QueryRequest::Custom(OrderbookQueryWrapper {
+            route: InjectiveRoute::Exchange,
+            query_data: OrderbookQuery::SpotOrderbook {
+                market_id: market_id.to_owned(),
+                limit: ORDERBOOK_LEVELS,
+                order_side,
+                limit_cumulative_quantity: Some(quantity),
+                limit_cumulative_notional: None,
+            },
+        }))?;
+
+    Ok(if is_buy(order_type) {
+        response.sells_price_level
+    } else {
+        response.buys_price_level
+    })
+}
+
+/// Largest part of `quantity` whose average fill price stays within `max_price_impact_bps` of
+/// the best price in `levels`.
+pub fn max_quantity_within_impact(
+    levels: &[PriceLevel],
+    quantity: FPDecimal,
+    max_price_impact_bps: u64,
+    buy: bool,
+) -> FPDecimal {
+    let best_price = match levels.first() {
+        Some(level) => level.p,
+        None => return FPDecimal::zero(),
+    };
+
+    let impact =
+        FPDecimal::from(max_price_impact_bps as u128) / FPDecimal::from(BPS_DENOMINATOR as u128);
+    let limit_price = if buy {
+        best_price * (FPDecimal::one() + impact)
+    } else {
+        best_price * (FPDecimal::one() - impact)
+    };
+
+    let within_limit = |price: FPDecimal| {
+        if buy {
+            price <= limit_price
+        } else {
+            price >= limit_price
+        }
+    };
+
+    let mut filled = FPDecimal::zero();
+    let mut notional = FPDecimal::zero();
+
+    for level in levels.iter().filter(|level| !level.q.is_zero()) {
+        let remaining = quantity - filled;
+        let take = if level.q < remaining {
+            level.q
+        } else {
+            remaining
+        };
+
+        if within_limit((notional + level.p * take) / (filled + take)) {
+            filled += take;
+            notional += level.p * take;
+        } else {
+            // Part of the level which brings the average price exactly to the limit
+            filled += (notional - limit_price * filled) / (limit_price - level.p);
+            break;
+        }
+
+        if filled == quantity {
+            break;
+        }
+    }
+
+    filled
+}
+
+/// Shrinks an order quantity, in base asset units, to what the book can fill within the price
+/// impact limit.
+pub fn depth_limited_quantity(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    market: &SpotMarket,
+    order_type: &OrderType,
+    quantity: Decimal,
+    base_decimals: u64,
+    max_price_impact_bps: u64,
+) -> StdResult<Decimal> {
+    let quantity_scale_factor = FPDecimal::from(10_i128.pow(base_decimals as u32));
+    let quantity_fp = FPDecimal::from_str(&quantity.to_string())? * quantity_scale_factor;
+
+    let levels = query_spot_orderbook(querier, &market.market_id, order_type, quantity_fp)?;
+    let fillable = max_quantity_within_impact(
+        &levels,
+        quantity_fp,
+        max_price_impact_bps,
+        is_buy(order_type),
+    );
+
+    if fillable == quantity_fp {
+        return Ok(quantity);
+    }
+
+    Decimal::from_str(&(fillable / quantity_scale_factor).to_string())
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use cosmwasm_std::{
+        Binary, ContractResult, Decimal, Querier, QuerierResult, QuerierWrapper, SystemResult,
+    };
+    use injective_cosmwasm::{InjectiveQueryWrapper, MarketId, OrderType, SpotMarket};
+    use injective_math::FPDecimal;
+
+    use super::{depth_limited_quantity, max_quantity_within_impact, PriceLevel};
+    use crate::tests::{INJUSDT_MARKET_ID, USDT};
+
+    /// Answers the raw orderbook query the chain would receive with a raw chain response.
+    struct MockOrderbookQuerier {
+        expected_request: String,
+        response: &'static str,
+    }
+
+    impl Querier for MockOrderbookQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            assert_eq!(
+                String::from_utf8(bin_request.to_vec()).unwrap(),
+                self.expected_request
+            );
+
+            SystemResult::Ok(ContractResult::Ok(Binary::from(self.response.as_bytes())))
+        }
+    }
+
+    fn market() -> SpotMarket {
+        SpotMarket {
+            ticker: "INJ/USDT".to_string(),
+            base_denom: "inj".to_string(),
+            quote_denom: USDT.to_string(),
+            maker_fee_rate: FPDecimal::from_str("0.001").unwrap(),
+            taker_fee_rate: FPDecimal::from_str("0.002").unwrap(),
+            relayer_fee_share_rate: FPDecimal::from_str("0.4").unwrap(),
+            market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+            status: 0,
+            min_price_tick_size: FPDecimal::from_str("0.001").unwrap(),
+            min_quantity_tick_size: FPDecimal::from_str("1000").unwrap(),
+        }
+    }
+
+    fn expected_request(order_side: i32) -> String {
+        format!(
+            "{{\"custom\":{{\"route\":\"exchange\",\"query_data\":{{\"spot_orderbook\":{{\
+            \"market_id\":\"{}\",\"limit\":50,\"order_side\":{},\
+            \"limit_cumulative_quantity\":\"1500000\",\"limit_cumulative_notional\":null}}}}}}}}",
+            INJUSDT_MARKET_ID, order_side
+        )
+    }
+
+    const ORDERBOOK_RESPONSE: &str = r#"{
+        "buys_price_level": [{"p": "10", "q": "1000000"}, {"p": "9", "q": "1000000"}],
+        "sells_price_level": [{"p": "10", "q": "1000000"}, {"p": "11", "q": "1000000"}]
+    }"#;
+
+    #[test]
+    fn depth_limited_quantity_from_orderbook() {
+        // Buys take the sell side, 0.25 out of the second level bring the average price to 10.2
+        let querier = MockOrderbookQuerier {
+            expected_request: expected_request(2),
+            response: ORDERBOOK_RESPONSE,
+        };
+        assert_eq!(
+            depth_limited_quantity(
+                &QuerierWrapper::<InjectiveQueryWrapper>::new(&querier),
+                &market(),
+                &OrderType::BuyAtomic,
+                Decimal::from_str("1.5").unwrap(),
+                6,
+                200,
+            )
+            .unwrap(),
+            Decimal::from_str("1.25").unwrap()
+        );
+
+        // Sells take the buy side, within a 5% impact the whole quantity fills
+        let querier = MockOrderbookQuerier {
+            expected_request: expected_request(1),
+            response: ORDERBOOK_RESPONSE,
+        };
+        assert_eq!(
+            depth_limited_quantity(
+                &QuerierWrapper::<InjectiveQueryWrapper>::new(&querier),
+                &market(),
+                &OrderType::SellAtomic,
+                Decimal::from_str("1.5").unwrap(),
+                6,
+                500,
+            )
+            .unwrap(),
+            Decimal::from_str("1.5").unwrap()
+        );
+    }
+
+    fn levels(levels: &[(u128, u128)]) -> Vec<PriceLevel> {
+        levels
+            .iter()
+            .map(|(p, q)| PriceLevel {
+                p: FPDecimal::from(*p),
+                q: FPDecimal::from(*q),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn buy_within_impact() {
+        let asks = levels(&[(10, 100), (11, 100)]);
+
+        // Average price of 10.33, within 5% of the best ask
+        assert_eq!(
+            max_quantity_within_impact(&asks, FPDecimal::from(150u128), 500, true),
+            FPDecimal::from(150u128)
+        );
+
+        // 25 out of the second level bring the average price to 10.2
+        assert_eq!(
+            max_quantity_within_impact(&asks, FPDecimal::from(150u128), 200, true),
+            FPDecimal::from(125u128)
+        );
+
+        // Not enough depth
+        assert_eq!(
+            max_quantity_within_impact(&asks, FPDecimal::from(500u128), 5_000, true),
+            FPDecimal::from(200u128)
+        );
+    }
+
+    #[test]
+    fn sell_within_impact() {
+        let bids = levels(&[(10, 100), (9, 100)]);
+
+        assert_eq!(
+            max_quantity_within_impact(&bids, FPDecimal::from(150u128), 500, false),
+            FPDecimal::from(150u128)
+        );
+
+        // 25 out of the second level bring the average price to 9.8
+        assert_eq!(
+            max_quantity_within_impact(&bids, FPDecimal::from(150u128), 200, false),
+            FPDecimal::from(125u128)
+        );
+
+        assert_eq!(
+            max_quantity_within_impact(&[], FPDecimal::from(150u128), 200, false),
+            FPDecimal::zero()
+        );
+    }
+}
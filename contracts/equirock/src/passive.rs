@@ -0,0 +1,319 @@
+use astroport::asset::AssetInfo;
+use cosmwasm_std::{
+    Coin, CosmosMsg, Decimal, DepsMut, Env, Event, MessageInfo, Response, StdError, StdResult,
+    Uint128,
+};
+use injective_cosmwasm::{
+    create_batch_update_orders_msg, create_withdraw_msg,
+    get_default_subaccount_id_for_checked_address, InjectiveMsgWrapper, InjectiveQuerier,
+    InjectiveQueryWrapper, OrderType, SpotOrder,
+};
+use injective_math::FPDecimal;
+
+use crate::{
+    execute::spot_order_data,
+    fees::accrue_fees,
+    helpers::{payout_msg, quote_asset},
+    ledger::{finish_operation, record_resting_order, start_operation},
+    oracle::check_oracle_deviation,
+    querier::{query_balance, query_decimals, query_token_info},
+    query::{basket_asset_limit_price, basket_asset_price, basket_value_usdt, pyth_price},
+    slippage::{buy_slippage, sell_slippage, MAX_SLIPPAGE_BPS},
+    state::{Basket, Config, OperationKind, OrderSide, RebalanceMode, BASKET},
+    ContractError,
+};
+
+impl RebalanceMode {
+    pub fn validate(&self) -> StdResult<()> {
+        match self {
+            RebalanceMode::Passive { offset_bps } if *offset_bps > MAX_SLIPPAGE_BPS => {
+                Err(StdError::generic_err(format!(
+                    "Passive order offset cannot exceed {} bps",
+                    MAX_SLIPPAGE_BPS
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replaces the open orders of the basket markets with post-only limit orders towards the target
+/// weights. The holdings include the contract subaccount deposits, i.e. the past fills and the
+/// funds of the cancelled orders, which are withdrawn to the contract. The operation ends with the
+/// orders resting on the book, and the keeper bounty is paid out of the deposit asset held.
+pub fn passive_rebalance(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+    offset_bps: u64,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let basket = BASKET.load(deps.storage)?;
+    let pricing = config.pricing.pricing(OperationKind::Rebalance);
+    let basket_value = basket_value_usdt(&deps.querier, &env, &config, &basket, pricing)?;
+
+    let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+    let fee_messages = accrue_fees(deps.storage, &env, &config, basket_value, total_supply)?;
+
+    let operation_id = start_operation(deps.storage, &env, OperationKind::Rebalance, &info.sender)?;
+
+    let contract = &env.contract.address;
+    let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
+    let injective_querier = InjectiveQuerier::new(&deps.querier);
+
+    let usdt_decimals = 6u64;
+
+    let mut withdraw_messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    let mut log: Vec<String> = vec![];
+    let mut events: Vec<Event> = vec![];
+
+    // Bank balance and subaccount deposit of an asset, withdrawing the available part
+    let mut holdings = |asset_info: &AssetInfo| -> StdResult<Uint128> {
+        let amount = query_balance(&deps.querier, asset_info, contract)?;
+
+        let denom = match asset_info {
+            AssetInfo::NativeToken { denom } => denom,
+            AssetInfo::Token { .. } => return Ok(amount),
+        };
+        let deposit = injective_querier
+            .query_subaccount_deposit(&subaccount_id, denom)?
+            .deposits;
+
+        let available: Uint128 = deposit.available_balance.into();
+        if !available.is_zero() {
+            withdraw_messages.push(create_withdraw_msg(
+                contract.to_owned(),
+                subaccount_id.to_owned(),
+                Coin::new(available.u128(), denom),
+            ));
+        }
+
+        Ok(amount.checked_add(deposit.total_balance.into())?)
+    };
+
+    let quote_amount = holdings(&quote_asset(&config)?)?;
+    let bounty = config.keeper.bounty.min(quote_amount);
+    let quote_amount = quote_amount - bounty;
+
+    // Quantity and oracle price of each basket asset
+    let mut positions = vec![];
+    for basket_asset in &basket.assets {
+        let amount = holdings(&basket_asset.asset.info)?;
+        let decimals = query_decimals(&deps.querier, &basket_asset.asset.info);
+        let quantity = Decimal::from_atomics(amount, decimals as u32)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let price = pyth_price(
+            basket_asset_price(&deps.querier, &env, &config, basket_asset)?.price(pricing)?,
+        )?;
+
+        positions.push((amount, quantity, price, decimals));
+    }
+
+    // The targets are shares of the whole basket value, the deposit asset held included
+    let total_value = Decimal::from_atomics(basket_value, usdt_decimals as u32)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let w_sum = basket
+        .assets
+        .iter()
+        .try_fold(Uint128::zero(), |acc, b| acc.checked_add(b.weight))?;
+
+    let mut sells: Vec<SpotOrder> = vec![];
+    let mut buys = vec![];
+    let mut retired_assets: Vec<AssetInfo> = vec![];
+
+    for (basket_asset, (amount, quantity, price, decimals)) in basket.assets.iter().zip(positions) {
+        let market = match injective_querier
+            .query_spot_market(&basket_asset.spot_market_id)?
+            .market
+        {
+            Some(market) => market,
+            None => continue,
+        };
+
+        let value = price.checked_mul(quantity)?;
+        let target_value = if w_sum.is_zero() {
+            Decimal::zero()
+        } else {
+            total_value.checked_mul(Decimal::from_ratio(basket_asset.weight, w_sum))?
+        };
+
+        // Retired asset which is fully liquidated (up to the unsellable dust)
+        if basket_asset.weight.is_zero() && FPDecimal::from(amount) < market.min_quantity_tick_size
+        {
+            retired_assets.push(basket_asset.asset.info.to_owned());
+            continue;
+        }
+
+        // Left alone while its weight is within the band of the target one
+        let drift_band = basket_asset.drift_band(basket.drift_band_bps);
+        if !basket_asset.weight.is_zero()
+            && value.abs_diff(target_value) <= drift_band.checked_mul(total_value)?
+        {
+            continue;
+        }
+
+        if let Some(event) = check_oracle_deviation(
+            &deps.querier,
+            &env,
+            &config,
+            basket_asset,
+            &market,
+            decimals,
+        )? {
+            events.push(event);
+        }
+
+        // On the maker side, selling above the oracle price and buying below it
+        if value > target_value {
+            let limit_price = basket_asset_limit_price(
+                &deps.querier,
+                &env,
+                &config,
+                basket_asset,
+                OperationKind::Rebalance,
+                OrderSide::Sell,
+            )?;
+            let order = spot_order_data(
+                buy_slippage(offset_bps)?,
+                limit_price,
+                (value - target_value)
+                    .checked_div(price)
+                    .map_err(|e| StdError::generic_err(e.to_string()))?,
+                &market,
+                decimals,
+                usdt_decimals,
+                OrderType::SellPo,
+                &subaccount_id,
+                contract,
+            )?;
+            sells.push(order);
+        } else {
+            let limit_price = basket_asset_limit_price(
+                &deps.querier,
+                &env,
+                &config,
+                basket_asset,
+                OperationKind::Rebalance,
+                OrderSide::Buy,
+            )?;
+            buys.push((
+                market,
+                limit_price,
+                (target_value - value)
+                    .checked_div(price)
+                    .map_err(|e| StdError::generic_err(e.to_string()))?,
+                decimals,
+            ));
+        }
+    }
+
+    // Buys are scaled down to the deposit asset held
+    let buy_price_ratio = sell_slippage(offset_bps)?;
+    let buy_value = buys
+        .iter()
+        .try_fold(Decimal::zero(), |acc, (_, price, quantity, _)| {
+            acc.checked_add(price.checked_mul(buy_price_ratio)?.checked_mul(*quantity)?)
+        })?;
+    let quote = Decimal::from_atomics(quote_amount, usdt_decimals as u32)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let fill_ratio = if buy_value.is_zero() || quote >= buy_value {
+        Decimal::one()
+    } else {
+        quote
+            .checked_div(buy_value)
+            .map_err(|e| StdError::generic_err(e.to_string()))?
+    };
+
+    let mut orders = sells;
+    for (market, price, quantity, decimals) in buys {
+        orders.push(spot_order_data(
+            buy_price_ratio,
+            price,
+            quantity.checked_mul(fill_ratio)?,
+            &market,
+            decimals,
+            usdt_decimals,
+            OrderType::BuyPo,
+            &subaccount_id,
+            contract,
+        )?);
+    }
+
+    // Orders rounded down to nothing
+    orders.retain(|order| !order.order_info.quantity.is_zero());
+
+    for order in &orders {
+        log.push(format!("order {:?}", order));
+
+        if let Some(basket_asset) = basket
+            .assets
+            .iter()
+            .find(|b| b.spot_market_id == order.market_id)
+        {
+            let side = if order.order_type == OrderType::SellPo {
+                OrderSide::Sell
+            } else {
+                OrderSide::Buy
+            };
+            record_resting_order(
+                deps.storage,
+                &basket_asset.asset.info,
+                &order.market_id,
+                side,
+            )?;
+        }
+    }
+    finish_operation(deps.storage, OperationKind::Rebalance)?;
+
+    let mut bounty_messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    if !bounty.is_zero() {
+        bounty_messages.push(payout_msg(&config, &info.sender, bounty)?);
+    }
+
+    let batch_update_msg = create_batch_update_orders_msg(
+        contract.to_owned(),
+        Some(subaccount_id),
+        basket
+            .assets
+            .iter()
+            .map(|b| b.spot_market_id.to_owned())
+            .collect(),
+        vec![],
+        vec![],
+        vec![],
+        orders,
+        vec![],
+    );
+
+    if !retired_assets.is_empty() {
+        BASKET.save(
+            deps.storage,
+            &Basket {
+                assets: basket
+                    .assets
+                    .into_iter()
+                    .filter(|b| !retired_assets.contains(&b.asset.info))
+                    .collect(),
+                drift_band_bps: basket.drift_band_bps,
+            },
+        )?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "rebalance")
+        .add_attribute("mode", "passive")
+        .add_attribute("operation_id", operation_id.to_string())
+        .add_attribute("bounty", bounty.to_string())
+        .add_attributes(
+            retired_assets
+                .iter()
+                .map(|asset| ("removed_asset", asset.to_string())),
+        )
+        .add_attribute("fill_ratio", fill_ratio.to_string())
+        .add_attribute("log", format!("{:?}", log))
+        .add_events(events)
+        .add_messages(fee_messages)
+        .add_messages(withdraw_messages)
+        .add_messages(bounty_messages)
+        .add_message(batch_update_msg))
+}
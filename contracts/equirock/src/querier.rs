@@ -1,7 +1,10 @@
 use astroport::asset::AssetInfo;
 use cosmwasm_std::{to_binary, Addr, QuerierWrapper, QueryRequest, StdResult, Uint128, WasmQuery};
 use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
-use injective_cosmwasm::{InjectiveQuerier, InjectiveQueryWrapper, QueryDenomDecimalResponse};
+use injective_cosmwasm::{
+    get_default_subaccount_id_for_checked_address, InjectiveQuerier, InjectiveQueryWrapper,
+    QueryDenomDecimalResponse,
+};
 use pyth_sdk_cw::{PriceFeedResponse, PriceIdentifier, QueryMsg};
 
 const DEFAULT_UNKNOWN_DECIMALS: u64 = 8u64;
@@ -88,6 +91,27 @@ pub fn query_balance(
     }
 }
 
+/// Balance of an asset plus its deposit in the default subaccount of the account, which holds
+/// the funds of the resting orders and the unwithdrawn fills.
+pub fn query_holdings(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    asset_info: &AssetInfo,
+    account_addr: &Addr,
+) -> StdResult<Uint128> {
+    let amount = query_balance(querier, asset_info, account_addr)?;
+
+    let denom = match asset_info {
+        AssetInfo::NativeToken { denom } => denom,
+        AssetInfo::Token { .. } => return Ok(amount),
+    };
+    let subaccount_id = get_default_subaccount_id_for_checked_address(account_addr);
+    let deposit = InjectiveQuerier::new(querier)
+        .query_subaccount_deposit(&subaccount_id, denom)?
+        .deposits;
+
+    Ok(amount.checked_add(deposit.total_balance.into())?)
+}
+
 pub fn query_price_feed(
     querier: &QuerierWrapper<InjectiveQueryWrapper>,
     contract_addr: Addr,
@@ -1,37 +1,140 @@
 use std::cmp::Ordering;
 
-use cosmwasm_std::{Addr, Decimal, Deps, Env, QuerierWrapper, StdError, StdResult, Uint128};
+use astroport::asset::AssetInfo;
+use cosmwasm_std::{Addr, Decimal, Deps, Env, Order, QuerierWrapper, StdError, StdResult, Uint128};
+use cw_storage_plus::Bound;
 use injective_cosmwasm::InjectiveQueryWrapper;
-use pyth_sdk_cw::{Price, PriceFeedResponse, PriceIdentifier};
+use pyth_sdk_cw::{Price, PriceFeedResponse};
 
 use crate::{
-    msg::{FetchPriceResponse, GetBasketAssetIdealRatioResponse},
-    querier::{query_balance, query_decimals, query_price_feed},
-    state::{Basket, BasketAsset, Config, BASKET, CONFIG},
+    fees::{pending_management_fee, pending_performance_fee},
+    helpers::quote_asset,
+    msg::{
+        AssetWeightResponse, FeedHealthResponse, FetchPriceResponse,
+        GetBasketAssetIdealRatioResponse, PendingFeesResponse,
+    },
+    oracle::{feed_health, Pricing},
+    querier::{query_balance, query_decimals, query_holdings, query_price_feed, query_token_info},
+    state::{
+        Basket, BasketAsset, Config, HighWaterMark, Operation, OperationKind, OrderSide,
+        PauseState, PendingChange, RebalancePlan, BASKET, CONFIG, HIGH_WATER_MARK, OPERATIONS,
+        PAUSE_STATE, PENDING_CHANGES, REBALANCE_PLAN,
+    },
 };
 
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
 pub fn config(deps: Deps<InjectiveQueryWrapper>) -> StdResult<Config> {
     let config = CONFIG.load(deps.storage)?;
     Ok(config)
 }
 
+pub fn pause_state(deps: Deps<InjectiveQueryWrapper>) -> StdResult<PauseState> {
+    Ok(PAUSE_STATE.may_load(deps.storage)?.unwrap_or_default())
+}
+
+pub fn get_pending_fees(
+    deps: Deps<InjectiveQueryWrapper>,
+    env: &Env,
+) -> StdResult<PendingFeesResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
+    let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+
+    let management_fee = pending_management_fee(deps.storage, env, &config, total_supply)?;
+
+    // The performance fee is charged after the management fee dilutes the holders
+    let basket_value = basket_value_usdt(
+        &deps.querier,
+        env,
+        &config,
+        &basket,
+        config.pricing.pricing(OperationKind::Rebalance),
+    )?;
+    let performance_fee = pending_performance_fee(
+        deps.storage,
+        &config,
+        basket_value,
+        total_supply.checked_add(management_fee)?,
+    )?;
+
+    Ok(PendingFeesResponse {
+        management_fee,
+        performance_fee,
+    })
+}
+
+pub fn rebalance_plan(deps: Deps<InjectiveQueryWrapper>) -> StdResult<Option<RebalancePlan>> {
+    REBALANCE_PLAN.may_load(deps.storage)
+}
+
+pub fn high_water_mark(deps: Deps<InjectiveQueryWrapper>) -> StdResult<Option<HighWaterMark>> {
+    HIGH_WATER_MARK.may_load(deps.storage)
+}
+
 pub fn get_basket_value(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<Uint128> {
     let config = CONFIG.load(deps.storage)?;
     let basket = BASKET.load(deps.storage)?;
 
-    let value_in_usdt = basket_value_usdt(&deps.querier, &env, &config, &basket)?;
+    let value_in_usdt = basket_value_usdt(&deps.querier, env, &config, &basket, Pricing::Spot)?;
 
     Ok(value_in_usdt)
 }
 
+pub fn get_pending_changes(
+    deps: Deps<InjectiveQueryWrapper>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<PendingChange>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    PENDING_CHANGES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, pending_change)| pending_change))
+        .collect()
+}
+
+pub fn get_feed_health(
+    deps: Deps<InjectiveQueryWrapper>,
+    env: &Env,
+) -> StdResult<Vec<FeedHealthResponse>> {
+    let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
+
+    feed_health(&deps.querier, env, &config, &basket)
+}
+
+pub fn operation(deps: Deps<InjectiveQueryWrapper>, id: u64) -> StdResult<Operation> {
+    OPERATIONS.load(deps.storage, id)
+}
+
+pub fn get_operations(
+    deps: Deps<InjectiveQueryWrapper>,
+    start_before: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<Operation>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_before.map(Bound::exclusive);
+
+    OPERATIONS
+        .range(deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|item| item.map(|(_, operation)| operation))
+        .collect()
+}
+
 pub fn get_basket_ideal_ratio(
     deps: Deps<InjectiveQueryWrapper>,
     env: &Env,
+    pricing: Pricing,
 ) -> StdResult<Vec<GetBasketAssetIdealRatioResponse>> {
     let config = CONFIG.load(deps.storage)?;
     let basket = BASKET.load(deps.storage)?;
 
-    let ratios = basket_ideal_state(&deps.querier, env, &config, &basket)?;
+    let ratios = basket_ideal_state(&deps.querier, env, &config, &basket, pricing)?;
 
     Ok(basket
         .assets
@@ -52,6 +155,7 @@ pub fn basket_ideal_state(
     env: &Env,
     config: &Config,
     basket: &Basket,
+    pricing: Pricing,
 ) -> StdResult<Vec<(Decimal, Decimal)>> {
     let w_sum = basket
         .assets
@@ -63,7 +167,7 @@ pub fn basket_ideal_state(
     let basket_asset_ratios = basket
         .assets
         .iter()
-        .map(|basket_asset| basket_asset_ratio(querier, env, config, basket_asset, w_sum))
+        .map(|basket_asset| basket_asset_ratio(querier, env, config, basket_asset, w_sum, pricing))
         .collect::<StdResult<Vec<(Decimal, Decimal)>>>()?;
 
     Ok(basket_asset_ratios)
@@ -75,15 +179,11 @@ pub fn basket_asset_ratio(
     config: &Config,
     basket_asset: &BasketAsset,
     w_sum: Uint128,
+    pricing: Pricing,
 ) -> StdResult<(Decimal, Decimal)> {
-    let fetch_price = basket_asset_price(
-        querier,
-        env,
-        &config.pyth_contract_addr,
-        basket_asset.pyth_price_feed,
-    )?;
+    let fetch_price = basket_asset_price(querier, env, config, basket_asset)?;
 
-    let price = pyth_price(fetch_price.current_price)?;
+    let price = pyth_price(fetch_price.price(pricing)?)?;
 
     let basket_asset_ratio = Decimal::from_ratio(
         basket_asset.weight,
@@ -100,8 +200,9 @@ pub fn basket_value_usdt(
     env: &Env,
     config: &Config,
     basket: &Basket,
+    pricing: Pricing,
 ) -> StdResult<Uint128> {
-    let value = basket_value(querier, env, config, basket)?;
+    let value = basket_value(querier, env, config, basket, pricing)?;
     let value_in_usdt_dec = value
         .checked_mul(
             Decimal::from_atomics(1_000_000u128, 0)
@@ -112,23 +213,167 @@ pub fn basket_value_usdt(
     Ok(value_in_usdt_dec)
 }
 
+/// Value of the basket assets and of the deposit asset held by the contract, in its bank balance
+/// and its subaccount.
 pub fn basket_value(
     querier: &QuerierWrapper<InjectiveQueryWrapper>,
     env: &Env,
     config: &Config,
     basket: &Basket,
+    pricing: Pricing,
 ) -> StdResult<Decimal> {
     let basket_asset_values = basket
         .assets
         .iter()
-        .map(|basket_asset| basket_asset_value(querier, env, config, basket_asset))
+        .map(|basket_asset| basket_asset_value(querier, env, config, basket_asset, pricing))
         .collect::<StdResult<Vec<Decimal>>>()?;
 
     let sum = basket_asset_values
         .iter()
         .try_fold(Decimal::zero(), |acc, value| acc.checked_add(*value))?;
 
-    Ok(sum)
+    Ok(sum.checked_add(deposit_value(querier, env, config)?)?)
+}
+
+/// Idle deposit asset held by the contract, e.g. what is left of the rebalance proceeds, valued at
+/// par.
+pub fn deposit_value(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+) -> StdResult<Decimal> {
+    let usdt_decimals = 6u32;
+    let deposit_balance = deposit_holdings(querier, config, &env.contract.address)?;
+
+    Decimal::from_atomics(deposit_balance, usdt_decimals)
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+/// Current and target weights of the basket assets, from their value held by the contract. The
+/// current weights are shares of the whole basket value, idle deposit asset included, so they add
+/// up to less than one while the contract holds some.
+pub fn asset_weights(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket: &Basket,
+    pricing: Pricing,
+) -> StdResult<Vec<AssetWeightResponse>> {
+    let w_sum = basket
+        .assets
+        .iter()
+        .try_fold(Uint128::zero(), |acc, basket_asset| {
+            acc.checked_add(basket_asset.weight)
+        })?;
+
+    let values = basket
+        .assets
+        .iter()
+        .map(|basket_asset| basket_asset_value(querier, env, config, basket_asset, pricing))
+        .collect::<StdResult<Vec<Decimal>>>()?;
+
+    let total_value = values
+        .iter()
+        .try_fold(Decimal::zero(), |acc, value| acc.checked_add(*value))?
+        .checked_add(deposit_value(querier, env, config)?)?;
+
+    basket
+        .assets
+        .iter()
+        .zip(values)
+        .map(|(basket_asset, value)| {
+            let current_weight = if total_value.is_zero() {
+                Decimal::zero()
+            } else {
+                value
+                    .checked_div(total_value)
+                    .map_err(|e| StdError::generic_err(e.to_string()))?
+            };
+            let target_weight = if w_sum.is_zero() {
+                Decimal::zero()
+            } else {
+                Decimal::from_ratio(basket_asset.weight, w_sum)
+            };
+            let drift_band = basket_asset.drift_band(basket.drift_band_bps);
+
+            // Retired assets are sold whatever their weight, until nothing is left
+            let within_band = if target_weight.is_zero() {
+                value.is_zero()
+            } else {
+                current_weight.abs_diff(target_weight) <= drift_band
+            };
+
+            Ok(AssetWeightResponse {
+                asset: basket_asset.asset.info.to_owned(),
+                value,
+                current_weight,
+                target_weight,
+                drift_band,
+                within_band,
+            })
+        })
+        .collect()
+}
+
+pub fn get_asset_weights(
+    deps: Deps<InjectiveQueryWrapper>,
+    env: &Env,
+) -> StdResult<Vec<AssetWeightResponse>> {
+    let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
+
+    asset_weights(
+        &deps.querier,
+        env,
+        &config,
+        &basket,
+        config.pricing.pricing(OperationKind::Rebalance),
+    )
+}
+
+/// Half the sum of the differences between the current and the target weights of the basket
+/// assets and of the idle deposit asset, whose target is none, i.e. the share of the basket value
+/// which would have to move to match the targets.
+pub fn tracking_error(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket: &Basket,
+    pricing: Pricing,
+) -> StdResult<Decimal> {
+    let weights = asset_weights(querier, env, config, basket, pricing)?;
+
+    let deposit_value = deposit_value(querier, env, config)?;
+    let total_value = weights
+        .iter()
+        .try_fold(deposit_value, |acc, w| acc.checked_add(w.value))?;
+
+    if total_value.is_zero() {
+        return Ok(Decimal::zero());
+    }
+
+    // The deposit asset weighs its share of the basket value against a target of none
+    let deposit_weight = deposit_value
+        .checked_div(total_value)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let deviation = weights.iter().try_fold(deposit_weight, |acc, w| {
+        acc.checked_add(w.current_weight.abs_diff(w.target_weight))
+    })?;
+
+    Ok(deviation.checked_mul(Decimal::percent(50))?)
+}
+
+pub fn get_tracking_error(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<Decimal> {
+    let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
+
+    tracking_error(
+        &deps.querier,
+        env,
+        &config,
+        &basket,
+        config.pricing.pricing(OperationKind::Rebalance),
+    )
 }
 
 pub fn pyth_price(price: Price) -> StdResult<Decimal> {
@@ -148,21 +393,47 @@ pub fn pyth_price(price: Price) -> StdResult<Decimal> {
     })
 }
 
+/// Deposit asset held by an account in its bank denom, including its subaccount deposits, and for
+/// a cw20 deposit asset in the cw20 tokens a deposit received before they are converted.
+pub fn deposit_holdings(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    config: &Config,
+    account_addr: &Addr,
+) -> StdResult<Uint128> {
+    let amount = query_holdings(querier, &quote_asset(config)?, account_addr)?;
+
+    match &config.deposit_asset {
+        AssetInfo::NativeToken { .. } => Ok(amount),
+        AssetInfo::Token { .. } => {
+            Ok(amount.checked_add(query_balance(querier, &config.deposit_asset, account_addr)?)?)
+        }
+    }
+}
+
 pub fn basket_asset_value(
     querier: &QuerierWrapper<InjectiveQueryWrapper>,
     env: &Env,
     config: &Config,
     basket_asset: &BasketAsset,
+    pricing: Pricing,
 ) -> StdResult<Decimal> {
-    let fetch_price = basket_asset_price(
-        querier,
-        env,
-        &config.pyth_contract_addr,
-        basket_asset.pyth_price_feed,
-    )?;
-    let price = pyth_price(fetch_price.current_price)?;
+    let amount = query_holdings(querier, &basket_asset.asset.info, &env.contract.address)?;
+
+    basket_asset_amount_value(querier, env, config, basket_asset, amount, pricing)
+}
+
+/// Pyth value of a given amount of a basket asset.
+pub fn basket_asset_amount_value(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket_asset: &BasketAsset,
+    amount: Uint128,
+    pricing: Pricing,
+) -> StdResult<Decimal> {
+    let fetch_price = basket_asset_price(querier, env, config, basket_asset)?;
+    let price = pyth_price(fetch_price.price(pricing)?)?;
 
-    let amount = query_balance(querier, &basket_asset.asset.info, &env.contract.address)?;
     let decimals = query_decimals(querier, &basket_asset.asset.info);
 
     let basket_asset_value = price.checked_mul(
@@ -173,22 +444,40 @@ pub fn basket_asset_value(
     Ok(basket_asset_value)
 }
 
+/// Oracle price an order of an operation on a basket asset is limited around.
+pub fn basket_asset_limit_price(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    env: &Env,
+    config: &Config,
+    basket_asset: &BasketAsset,
+    kind: OperationKind,
+    side: OrderSide,
+) -> StdResult<Decimal> {
+    let fetch_price = basket_asset_price(querier, env, config, basket_asset)?;
+
+    pyth_price(fetch_price.price(config.pricing.limit_pricing(kind, side))?)
+}
+
 pub fn basket_asset_price(
     querier: &QuerierWrapper<InjectiveQueryWrapper>,
     env: &Env,
-    pyth_contract_addr: &Addr,
-    price_feed_id: PriceIdentifier,
+    config: &Config,
+    basket_asset: &BasketAsset,
 ) -> StdResult<FetchPriceResponse> {
-    let price_feed_response: PriceFeedResponse =
-        query_price_feed(querier, pyth_contract_addr.to_owned(), price_feed_id)?;
+    let price_feed_response: PriceFeedResponse = query_price_feed(
+        querier,
+        config.pyth_contract_addr.to_owned(),
+        basket_asset.pyth_price_feed,
+    )?;
     let price_feed = price_feed_response.price_feed;
+    let max_age = basket_asset.oracle(&config.oracle).max_age;
 
     let current_price = price_feed
-        .get_price_no_older_than(env.block.time.seconds() as i64, 60)
+        .get_price_no_older_than(env.block.time.seconds() as i64, max_age)
         .ok_or_else(|| StdError::not_found("Current price is not available"))?;
 
     let ema_price = price_feed
-        .get_ema_price_no_older_than(env.block.time.seconds() as i64, 60)
+        .get_ema_price_no_older_than(env.block.time.seconds() as i64, max_age)
         .ok_or_else(|| StdError::not_found("EMA price is not available"))?;
 
     Ok(FetchPriceResponse {
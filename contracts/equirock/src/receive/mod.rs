@@ -1,29 +1,70 @@
-use cosmwasm_std::{from_binary, DepsMut, Env, MessageInfo, Response, StdError, StdResult};
+use astroport::asset::{Asset, AssetInfo};
+use cosmwasm_std::{from_binary, DepsMut, Env, MessageInfo, Response};
 
 mod withdraw;
+mod withdraw_in_kind;
 
 use cw20::Cw20ReceiveMsg;
 use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
 use withdraw::withdraw;
+use withdraw_in_kind::withdraw_in_kind;
 
-use crate::{msg::Cw20HookMsg, state::CONFIG};
+use crate::{
+    execute::deposit_into_basket, ledger::check_idle, msg::Cw20HookMsg, state::CONFIG,
+    ContractError,
+};
 
 pub fn receive(
     deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
     info: MessageInfo,
     msg: Cw20ReceiveMsg,
-) -> StdResult<Response<InjectiveMsgWrapper>> {
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     let config = CONFIG.load(deps.storage)?;
 
-    if info.sender != config.lp_token {
-        return Err(StdError::GenericErr {
-            msg: "Unauthorized".to_string(),
-        });
-    }
+    check_idle(deps.storage)?;
+
+    let deposit_token = AssetInfo::Token {
+        contract_addr: info.sender.to_owned(),
+    };
 
     match from_binary(&msg.msg)? {
-        Cw20HookMsg::Withdraw {} => withdraw(deps, env, msg.sender, msg.amount),
+        Cw20HookMsg::Withdraw {
+            min_received,
+            deadline,
+            max_slippage_bps,
+        } if info.sender == config.lp_token => withdraw(
+            deps,
+            env,
+            msg.sender,
+            msg.amount,
+            min_received,
+            deadline,
+            max_slippage_bps,
+        ),
+        Cw20HookMsg::WithdrawInKind {} if info.sender == config.lp_token => {
+            Ok(withdraw_in_kind(deps, env, msg.sender, msg.amount)?)
+        }
+        Cw20HookMsg::Deposit {
+            min_lp_out,
+            deadline,
+            max_slippage_bps,
+        } if deposit_token == config.deposit_asset => {
+            let sender = deps.api.addr_validate(&msg.sender)?;
+            deposit_into_basket(
+                deps,
+                env,
+                sender,
+                Asset {
+                    info: deposit_token,
+                    amount: msg.amount,
+                },
+                min_lp_out,
+                deadline,
+                max_slippage_bps,
+            )
+        }
+        _ => Err(ContractError::Unauthorized {}),
     }
 }
 
@@ -31,81 +72,372 @@ pub fn receive(
 mod test {
     use std::time::Duration;
 
-    use astroport::asset::AssetInfo;
-    use cosmwasm_std::{coins, testing::mock_info, to_binary, Addr, Coin, Uint128};
-    use pyth_sdk_cw::testing::MockPyth;
+    use astroport::asset::{Asset, AssetInfo};
+    use cosmwasm_std::{
+        coins, from_binary, testing::mock_info, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg,
+        Uint128, WasmMsg,
+    };
+    use cw20::Cw20ExecuteMsg;
+    use injective_cosmwasm::{
+        create_withdraw_msg, get_default_subaccount_id_for_checked_address, MarketId,
+    };
+    use pyth_sdk_cw::{testing::MockPyth, PriceIdentifier};
 
     use crate::{
         contract::execute,
-        msg::{Cw20HookMsg, ExecuteMsg},
-        state::{Config, BASKET, CONFIG},
-        tests::{setup_test, LP_TOKEN_ADDR, USDT},
+        msg::{CallbackMsg, Cw20HookMsg, ExecuteMsg},
+        state::{Basket, BasketAsset, Config, BASKET, CONFIG},
+        tests::{
+            create_balance_bank_handler, create_subaccount_deposit_handler, mock_config,
+            setup_test, CONTRACT_ADDR, CW20_ADAPTER_ADDR, INJUSDT_MARKET_ID, LP_TOKEN_ADDR,
+            PRICE_ID_INJ, USDT, USDT_TOKEN_ADDR, USDT_TOKEN_BALANCE,
+        },
+        ContractError,
     };
 
+    fn token_config() -> Config {
+        Config {
+            deposit_asset: AssetInfo::Token {
+                contract_addr: Addr::unchecked(USDT_TOKEN_ADDR),
+            },
+            cw20_adapter: Some(Addr::unchecked(CW20_ADAPTER_ADDR)),
+            ..mock_config()
+        }
+    }
+
     #[test]
     #[should_panic(expected = "Unauthorized")]
     fn unauthorized() {
         let current_unix_time = 10_000_000;
         let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
         let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+        CONFIG.save(&mut deps.storage, &mock_config()).unwrap();
 
-        let mock_address = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
+        let auth_info = mock_info("anyone", &coins(1, USDT.to_owned()));
+        let msg: ExecuteMsg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: auth_info.sender.to_owned().into_string(),
+            amount: Uint128::new(1_000),
+            msg: to_binary(&Cw20HookMsg::Withdraw {
+                min_received: None,
+                deadline: None,
+                max_slippage_bps: None,
+            })
+            .unwrap(),
+        });
 
-        CONFIG
+        let _res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
+    }
+
+    #[test]
+    fn authorized() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+        BASKET
             .save(
                 &mut deps.storage,
-                &Config {
-                    lp_token: mock_address,
-                    deposit_asset: AssetInfo::NativeToken {
-                        denom: USDT.to_owned(),
-                    },
-                    pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
+                &crate::state::Basket {
+                    assets: vec![],
+                    drift_band_bps: 0,
                 },
             )
             .unwrap();
 
-        let auth_info = mock_info("anyone", &coins(1, USDT.to_owned()));
+        CONFIG.save(&mut deps.storage, &mock_config()).unwrap();
+
+        let auth_info = mock_info(LP_TOKEN_ADDR, &coins(1, USDT.to_owned()));
         let msg: ExecuteMsg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
             sender: auth_info.sender.to_owned().into_string(),
             amount: Uint128::new(1_000),
-            msg: to_binary(&Cw20HookMsg::Withdraw {}).unwrap(),
+            msg: to_binary(&Cw20HookMsg::Withdraw {
+                min_received: None,
+                deadline: None,
+                max_slippage_bps: None,
+            })
+            .unwrap(),
         });
 
         let _res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
     }
 
     #[test]
-    fn authorized() {
+    fn deposit_token() {
         let current_unix_time = 10_000_000;
         let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
         let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
 
-        let mock_address = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
-
         BASKET
-            .save(&mut deps.storage, &crate::state::Basket { assets: vec![] })
+            .save(
+                &mut deps.storage,
+                &crate::state::Basket {
+                    assets: vec![],
+                    drift_band_bps: 0,
+                },
+            )
+            .unwrap();
+        CONFIG.save(&mut deps.storage, &token_config()).unwrap();
+        // Nothing converted through the adapter yet
+        deps.querier.balance_query_handler =
+            Some(Box::new(create_balance_bank_handler(Some(|_| 0))));
+
+        // The whole token balance was just deposited
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: String::from("depositor"),
+            amount: Uint128::new(USDT_TOKEN_BALANCE),
+            msg: to_binary(&Cw20HookMsg::Deposit {
+                min_lp_out: None,
+                deadline: None,
+                max_slippage_bps: None,
+            })
+            .unwrap(),
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(USDT_TOKEN_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+
+        // Converted to its bank denom before the orders
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from(USDT_TOKEN_ADDR),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: String::from(CW20_ADAPTER_ADDR),
+                    amount: Uint128::new(USDT_TOKEN_BALANCE),
+                    msg: Binary::default(),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+
+        let after_deposit = res
+            .messages
+            .iter()
+            .find_map(|sub_msg| match &sub_msg.msg {
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr, msg, ..
+                }) if contract_addr == CONTRACT_ADDR => from_binary::<ExecuteMsg>(msg).ok(),
+                _ => None,
+            })
             .unwrap();
+        assert!(matches!(
+            after_deposit,
+            ExecuteMsg::Callback(CallbackMsg::AfterDeposit { deposit, sender, basket_value, .. })
+                if deposit == Uint128::new(USDT_TOKEN_BALANCE)
+                    && sender == Addr::unchecked("depositor")
+                    && basket_value.is_zero()
+        ));
+    }
+
+    #[test]
+    fn deposit_token_unauthorized() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        CONFIG.save(&mut deps.storage, &token_config()).unwrap();
+
+        // Any other token
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: String::from("depositor"),
+            amount: Uint128::new(1_000),
+            msg: to_binary(&Cw20HookMsg::Deposit {
+                min_lp_out: None,
+                deadline: None,
+                max_slippage_bps: None,
+            })
+            .unwrap(),
+        });
+        let err = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info("other-token", &[]),
+            msg,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // Tokens cannot be deposited without being sent
+        let msg = ExecuteMsg::Deposit {
+            asset: Asset {
+                info: AssetInfo::Token {
+                    contract_addr: Addr::unchecked(USDT_TOKEN_ADDR),
+                },
+                amount: Uint128::new(1_000),
+            },
+            min_lp_out: None,
+            deadline: None,
+            max_slippage_bps: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info("depositor", &[]),
+            msg,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Generic error: Tokens are deposited with cw20 Send"
+        );
+    }
+
+    #[test]
+    fn deposit_token_deadline() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+        CONFIG.save(&mut deps.storage, &token_config()).unwrap();
 
-        CONFIG
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: String::from("depositor"),
+            amount: Uint128::new(1_000),
+            msg: to_binary(&Cw20HookMsg::Deposit {
+                min_lp_out: None,
+                deadline: Some(env.block.time.minus_seconds(1)),
+                max_slippage_bps: None,
+            })
+            .unwrap(),
+        });
+        let err = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(USDT_TOKEN_ADDR, &[]),
+            msg,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::DeadlineExpired { .. }));
+    }
+
+    #[test]
+    fn withdraw_in_kind() {
+        let current_unix_time = 10_000_000;
+        // No price feeds, the oracle is not needed
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+        deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+            |denom| match denom {
+                "inj" => 1_000,
+                _ => 500,
+            },
+        ))));
+
+        BASKET
             .save(
                 &mut deps.storage,
-                &Config {
-                    lp_token: mock_address,
-                    deposit_asset: AssetInfo::NativeToken {
-                        denom: USDT.to_owned(),
-                    },
-                    pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
+                &Basket {
+                    assets: vec![BasketAsset {
+                        asset: Asset {
+                            info: AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            },
+                            amount: Uint128::zero(),
+                        },
+                        pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                        weight: Uint128::from(1u128),
+                        spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                        slippage: None,
+                        drift_band_bps: None,
+                        oracle: None,
+                    }],
+                    drift_band_bps: 0,
                 },
             )
             .unwrap();
 
-        let auth_info = mock_info(LP_TOKEN_ADDR, &coins(1, USDT.to_owned()));
-        let msg: ExecuteMsg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: auth_info.sender.to_owned().into_string(),
-            amount: Uint128::new(1_000),
-            msg: to_binary(&Cw20HookMsg::Withdraw {}).unwrap(),
+        let mut config = token_config();
+        config.deposit_asset = AssetInfo::NativeToken {
+            denom: USDT.to_owned(),
+        };
+        config.fees.exit_fee_bps = 100;
+        CONFIG.save(&mut deps.storage, &config).unwrap();
+
+        // 10 out of the 100 LP tokens in supply
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: String::from("user"),
+            amount: Uint128::new(10),
+            msg: to_binary(&Cw20HookMsg::WithdrawInKind {}).unwrap(),
         });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(LP_TOKEN_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
 
-        let _res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
+        let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: LP_TOKEN_ADDR.to_owned(),
+                    msg: to_binary(&cw20::Cw20ExecuteMsg::Burn {
+                        amount: Uint128::new(10)
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("collector"),
+                    amount: coins(1, "inj"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("user"),
+                    amount: coins(99, "inj"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("user"),
+                    amount: coins(50, USDT),
+                }),
+            ]
+        );
+
+        // The INJ held in the subaccount is withdrawn before being paid out
+        deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+            |denom| match denom {
+                "inj" => 40,
+                _ => 500,
+            },
+        ))));
+        deps.querier.subaccount_deposit_response_handler = Some(Box::new(
+            create_subaccount_deposit_handler(|denom| match denom {
+                "inj" => 960,
+                _ => 0,
+            }),
+        ));
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: String::from("user"),
+            amount: Uint128::new(10),
+            msg: to_binary(&Cw20HookMsg::WithdrawInKind {}).unwrap(),
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.to_owned(),
+            mock_info(LP_TOKEN_ADDR, &[]),
+            msg,
+        )
+        .unwrap();
+        let contract = Addr::unchecked(CONTRACT_ADDR);
+        assert_eq!(
+            res.messages[1].msg,
+            create_withdraw_msg(
+                contract.to_owned(),
+                get_default_subaccount_id_for_checked_address(&contract),
+                Coin::new(60, "inj"),
+            )
+        );
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("collector"),
+                amount: coins(1, "inj"),
+            })
+        );
     }
 }
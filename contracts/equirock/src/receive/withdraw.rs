@@ -1,7 +1,7 @@
 use astroport::asset::AssetInfo;
 use cosmwasm_std::{
-    to_binary, BankMsg, Coin, CosmosMsg, Decimal, DepsMut, Env, Response, StdError, StdResult,
-    SubMsg, Uint128, WasmMsg,
+    to_binary, CosmosMsg, Decimal, DepsMut, Env, Event, Response, StdError, SubMsg, Timestamp,
+    Uint128, WasmMsg,
 };
 use cw20::{Cw20ExecuteMsg, Cw20QueryMsg, TokenInfoResponse};
 use injective_cosmwasm::{
@@ -11,22 +11,71 @@ use injective_cosmwasm::{
 
 use crate::{
     execute::spot_order,
+    fees::accrue_management_fee,
+    helpers::{payout_msg, quote_asset, subaccount_withdraw_msgs},
+    ledger::{record_order, start_operation},
     msg::{CallbackMsg, ExecuteMsg},
-    querier::{query_balance, query_decimals},
-    query::{basket_asset_price, pyth_price},
-    reply::ATOMIC_ORDER_REPLY_ID,
-    state::{ClobCache, BASKET, CLOB_CACHE, CONFIG},
+    oracle::{check_feeds, check_oracle_deviation},
+    orderbook::depth_limited_quantity,
+    querier::{query_decimals, query_holdings},
+    query::basket_asset_limit_price,
+    reply::order_submsg,
+    slippage::{check_max_slippage, sell_slippage},
+    state::{OperationKind, OrderSide, BASKET, CONFIG, PAUSE_STATE},
+    ContractError,
 };
 
+use super::withdraw_in_kind::withdraw_in_kind;
+
 pub fn withdraw(
     deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
     sender: String,
     amount: Uint128,
-) -> StdResult<Response<InjectiveMsgWrapper>> {
+    min_received: Option<Uint128>,
+    deadline: Option<Timestamp>,
+    max_slippage_bps: Option<u64>,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    if let Some(deadline) = deadline {
+        if env.block.time > deadline {
+            return Err(ContractError::DeadlineExpired {
+                deadline: deadline.seconds(),
+            });
+        }
+    }
+
+    // Selling is paused, users can still exit with their share of the basket
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .withdraw
+    {
+        // Proceeds in kind cannot be held to a minimum of the deposit asset
+        if min_received.is_some() {
+            return Err(StdError::generic_err(
+                "Withdrawals are paused, use WithdrawInKind without a minimum received",
+            )
+            .into());
+        }
+
+        return Ok(withdraw_in_kind(deps, env, sender, amount)?);
+    }
+
     let config = CONFIG.load(deps.storage)?;
 
     let sender = deps.api.addr_validate(&sender)?;
+
+    let basket = BASKET.load(deps.storage)?;
+    check_feeds(&deps.querier, &env, &config, &basket)?;
+
+    let total_share: TokenInfoResponse = deps
+        .querier
+        .query_wasm_smart(&config.lp_token, &Cw20QueryMsg::TokenInfo {})?;
+
+    // The withdrawn share is diluted by the fee minted in the same transaction
+    let (management_fee, fee_messages) =
+        accrue_management_fee(deps.storage, &env, &config, total_share.total_supply)?;
+    let total_supply = total_share.total_supply.checked_add(management_fee)?;
     // let collected_protocol_fees = COLLECTED_PROTOCOL_FEES.load(deps.storage)?;
     // let total_asset_amount = match &config.asset_info {
     //     AssetInfo::NativeToken { denom } => {
@@ -46,23 +95,19 @@ pub fn withdraw(
     // } // deduct protocol fees
     // .checked_sub(collected_protocol_fees.amount)?;
 
-    let total_share: TokenInfoResponse = deps
-        .querier
-        .query_wasm_smart(&config.lp_token, &Cw20QueryMsg::TokenInfo {})?;
-
-    CLOB_CACHE.save(deps.storage, &vec![ClobCache::new()])?;
-
-    let basket = BASKET.load(deps.storage)?;
-    let withdraw_ratio = Decimal::from_ratio(amount, total_share.total_supply);
+    let operation_id = start_operation(deps.storage, &env, OperationKind::Withdraw, &sender)?;
+    let withdraw_ratio = Decimal::from_ratio(amount, total_supply);
 
     let contract = &env.contract.address;
     let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
-    let slippage = Decimal::one().checked_sub(Decimal::from_ratio(5u128, 100u128))?;
     let mut submessages: Vec<SubMsg<InjectiveMsgWrapper>> = vec![];
     let injective_querier = InjectiveQuerier::new(&deps.querier);
 
     let mut log: Vec<String> = vec![];
-    for basket_asset in basket.assets {
+    let mut events: Vec<Event> = vec![];
+    // Amounts sold or paid out, which the subaccount makes up for when the bank balance lacks them
+    let mut payouts: Vec<(AssetInfo, Uint128)> = vec![];
+    for (index, basket_asset) in basket.assets.iter().enumerate() {
         let market = injective_querier.query_spot_market(&basket_asset.spot_market_id)?;
         if let Some(market) = market.market {
             log.push(format!("{:?}", market));
@@ -76,28 +121,72 @@ pub fn withdraw(
 
             log.push(format!("base_decimals {:?}", base_decimals));
 
-            let fetch_price = basket_asset_price(
+            let price = basket_asset_limit_price(
                 &deps.querier,
                 &env,
-                &config.pyth_contract_addr,
-                basket_asset.pyth_price_feed,
+                &config,
+                basket_asset,
+                OperationKind::Withdraw,
+                OrderSide::Sell,
             )?;
-            let price = pyth_price(fetch_price.current_price)?;
 
-            let amount = query_balance(
+            let amount = query_holdings(
                 &deps.querier,
                 &basket_asset.asset.info,
                 &env.contract.address,
             )?;
 
+            // Nothing to sell, e.g. an asset added since the last deposit
+            if amount.is_zero() {
+                continue;
+            }
+
+            let slippage = basket_asset.slippage(&config.slippage);
+            check_max_slippage(max_slippage_bps, slippage.withdraw_bps)?;
+            let slippage_bps = slippage
+                .withdraw_bps
+                .min(max_slippage_bps.unwrap_or(u64::MAX));
+
+            let quantity = withdraw_ratio.checked_mul(
+                Decimal::from_atomics(amount, base_decimals as u32)
+                    .map_err(|e| StdError::generic_err(e.to_string()))?,
+                // .checked_mul(Decimal::from_str("0.998")?)?,
+            )?;
+
+            // A partial sale would leave the rest of the user's share in the vault
+            if let Some(max_price_impact_bps) = slippage.max_price_impact_bps {
+                if depth_limited_quantity(
+                    &deps.querier,
+                    &market,
+                    &OrderType::SellAtomic,
+                    quantity,
+                    base_decimals,
+                    max_price_impact_bps,
+                )? < quantity
+                {
+                    return Err(StdError::generic_err(format!(
+                        "Orderbook of market {} is too thin, use WithdrawInKind",
+                        market.market_id.as_str()
+                    ))
+                    .into());
+                }
+            }
+
+            if let Some(event) = check_oracle_deviation(
+                &deps.querier,
+                &env,
+                &config,
+                basket_asset,
+                &market,
+                base_decimals,
+            )? {
+                events.push(event);
+            }
+
             let order_msg = spot_order(
-                slippage,
+                sell_slippage(slippage_bps)?,
                 price,
-                withdraw_ratio.checked_mul(
-                    Decimal::from_atomics(amount, base_decimals as u32)
-                        .map_err(|e| StdError::generic_err(e.to_string()))?,
-                    // .checked_mul(Decimal::from_str("0.998")?)?,
-                )?,
+                quantity,
                 &market,
                 base_decimals,
                 quote_decimals,
@@ -108,50 +197,65 @@ pub fn withdraw(
 
             log.push(format!("order_msg {:?}", order_msg));
 
-            let order_message = SubMsg::reply_on_success(order_msg, ATOMIC_ORDER_REPLY_ID);
-            submessages.push(order_message);
+            record_order(
+                deps.storage,
+                &basket_asset.asset.info,
+                &market.market_id,
+                OrderSide::Sell,
+            )?;
+            submessages.push(order_submsg(order_msg, index));
+            payouts.push((basket_asset.asset.info.to_owned(), amount * withdraw_ratio));
         }
     }
 
+    let quote_asset = quote_asset(&config)?;
+    let deposit_amount = query_holdings(&deps.querier, &quote_asset, &env.contract.address)?;
+
+    let deposit_to_withdraw = withdraw_ratio
+        .checked_mul(Decimal::from_atomics(deposit_amount, 0u32).unwrap())?
+        .to_uint_floor();
+    payouts.push((quote_asset, deposit_to_withdraw));
+    let subaccount_messages = subaccount_withdraw_msgs(&deps.querier, contract, &basket, &payouts)?;
+
+    let exit_fee = config.fees.exit_fee(&sender, deposit_to_withdraw);
+    let deposit_to_withdraw = deposit_to_withdraw.checked_sub(exit_fee)?;
+
+    // The share of the deposit asset held by the contract counts towards the minimum, the
+    // callback only checks the sale proceeds
     let after_withdraw_msg = WasmMsg::Execute {
         contract_addr: contract.to_owned().into_string(),
         msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
             sender: sender.clone(),
+            min_received: min_received.map(|min| min.saturating_sub(deposit_to_withdraw)),
         }))?,
         funds: vec![],
     };
 
     let burn_lp_tokens_msg = WasmMsg::Execute {
-        contract_addr: config.lp_token.into_string(),
+        contract_addr: config.lp_token.to_string(),
         funds: vec![],
         msg: to_binary(&Cw20ExecuteMsg::Burn { amount })?,
     };
 
     let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> =
         vec![after_withdraw_msg.into(), burn_lp_tokens_msg.into()];
+    messages.extend(fee_messages);
 
-    let deposit_amount =
-        query_balance(&deps.querier, &config.deposit_asset, &env.contract.address)?;
-
-    let deposit_to_withdraw = withdraw_ratio
-        .checked_mul(Decimal::from_atomics(deposit_amount, 0u32).unwrap())?
-        .to_uint_floor();
-
-    if let AssetInfo::NativeToken { denom } = config.deposit_asset {
-        if deposit_to_withdraw.gt(&Uint128::zero()) {
-            let withdraw_deposit_coins = Coin::new(u128::from(deposit_to_withdraw), denom);
-
-            let send_message = CosmosMsg::Bank(BankMsg::Send {
-                to_address: sender.clone().into_string(),
-                amount: vec![withdraw_deposit_coins],
-            });
+    if !exit_fee.is_zero() {
+        messages.push(payout_msg(&config, &config.fees.collector, exit_fee)?);
+    }
 
-            messages.push(send_message);
-        }
+    if deposit_to_withdraw.gt(&Uint128::zero()) {
+        messages.push(payout_msg(&config, &sender, deposit_to_withdraw)?);
     }
 
     Ok(Response::new()
-        .add_attributes(vec![("method", "withdraw")])
+        .add_attributes(vec![
+            ("method", "withdraw".to_string()),
+            ("operation_id", operation_id.to_string()),
+        ])
+        .add_events(events)
+        .add_messages(subaccount_messages)
         .add_submessages(submessages)
         .add_messages(messages))
 }
@@ -161,17 +265,17 @@ mod test {
     use std::time::Duration;
 
     use astroport::asset::{Asset, AssetInfo};
-    use cosmwasm_std::{coins, testing::mock_info, to_binary, Addr, Coin, Uint128};
+    use cosmwasm_std::{coins, testing::mock_info, to_binary, BankMsg, Coin, CosmosMsg, Uint128};
     use injective_cosmwasm::MarketId;
     use pyth_sdk_cw::{testing::MockPyth, Price, PriceFeed, PriceIdentifier};
 
     use crate::{
         contract::execute,
         msg::{Cw20HookMsg, ExecuteMsg},
-        state::{BasketAsset, Config, BASKET, CONFIG},
+        state::{BasketAsset, PauseState, BASKET, CONFIG, PAUSE_STATE},
         tests::{
-            setup_test, ATOMUSDT_MARKET_ID, INJUSDT_MARKET_ID, LP_TOKEN_ADDR, PRICE_ID_ATOM,
-            PRICE_ID_INJ, PYTH_CONTRACT_ADDR, USDT,
+            create_balance_bank_handler, mock_config, setup_test, ATOMUSDT_MARKET_ID,
+            INJUSDT_MARKET_ID, LP_TOKEN_ADDR, PRICE_ID_ATOM, PRICE_ID_INJ, USDT,
         },
     };
 
@@ -213,9 +317,6 @@ mod test {
         mock_pyth.add_feed(price_feed_inj);
         mock_pyth.add_feed(price_feed_atom);
         let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
-
-        let mock_address = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
-
         BASKET
             .save(
                 &mut deps.storage,
@@ -233,6 +334,9 @@ mod test {
                             pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
                             weight: Uint128::from(1u128),
                             spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                            slippage: None,
+                            drift_band_bps: None,
+                            oracle: None,
                         },
                         BasketAsset {
                             asset: Asset {
@@ -246,34 +350,123 @@ mod test {
                             pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
                             weight: Uint128::from(1u128),
                             spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                            slippage: None,
+                            drift_band_bps: None,
+                            oracle: None,
                         },
                     ],
+                    drift_band_bps: 0,
                 },
             )
             .unwrap();
 
-        CONFIG
+        CONFIG.save(&mut deps.storage, &mock_config()).unwrap();
+
+        let auth_info = mock_info(LP_TOKEN_ADDR, &coins(1, USDT.to_owned()));
+        let msg: ExecuteMsg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: auth_info.sender.to_owned().into_string(),
+            amount: Uint128::new(10),
+            msg: to_binary(&Cw20HookMsg::Withdraw {
+                min_received: None,
+                deadline: None,
+                max_slippage_bps: None,
+            })
+            .unwrap(),
+        });
+
+        let res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
+
+        println!("{:?}", res.messages);
+    }
+
+    #[test]
+    fn withdraw_paused() {
+        let current_unix_time = 10_000_000;
+        let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+        let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+        deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+            |denom| match denom {
+                "inj" => 1_000,
+                _ => 500,
+            },
+        ))));
+
+        BASKET
             .save(
                 &mut deps.storage,
-                &Config {
-                    lp_token: mock_address,
-                    deposit_asset: AssetInfo::NativeToken {
-                        denom: USDT.to_owned(),
-                    },
-                    pyth_contract_addr: Addr::unchecked(PYTH_CONTRACT_ADDR),
+                &crate::state::Basket {
+                    assets: vec![BasketAsset {
+                        asset: Asset {
+                            info: AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            },
+                            amount: Uint128::zero(),
+                        },
+                        pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                        weight: Uint128::from(1u128),
+                        spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                        slippage: None,
+                        drift_band_bps: None,
+                        oracle: None,
+                    }],
+                    drift_band_bps: 0,
                 },
             )
             .unwrap();
 
-        let auth_info = mock_info(LP_TOKEN_ADDR, &coins(1, USDT.to_owned()));
+        CONFIG.save(&mut deps.storage, &mock_config()).unwrap();
+
+        PAUSE_STATE
+            .save(
+                &mut deps.storage,
+                &PauseState {
+                    deposit: false,
+                    withdraw: true,
+                    rebalance: false,
+                },
+            )
+            .unwrap();
+
+        // 10 out of the 100 LP tokens in supply
+        let auth_info = mock_info(LP_TOKEN_ADDR, &[]);
         let msg: ExecuteMsg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: auth_info.sender.to_owned().into_string(),
+            sender: String::from("user"),
             amount: Uint128::new(10),
-            msg: to_binary(&Cw20HookMsg::Withdraw {}).unwrap(),
+            msg: to_binary(&Cw20HookMsg::Withdraw {
+                min_received: None,
+                deadline: None,
+                max_slippage_bps: None,
+            })
+            .unwrap(),
         });
 
         let res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
 
-        println!("{:?}", res.messages);
+        let bank_msgs: Vec<&BankMsg> = res
+            .messages
+            .iter()
+            .filter_map(|sub_msg| match &sub_msg.msg {
+                CosmosMsg::Bank(bank_msg) => Some(bank_msg),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            bank_msgs,
+            vec![
+                &BankMsg::Send {
+                    to_address: String::from("user"),
+                    amount: coins(100, "inj"),
+                },
+                &BankMsg::Send {
+                    to_address: String::from("user"),
+                    amount: coins(50, USDT),
+                },
+            ]
+        );
+        assert!(!res
+            .messages
+            .iter()
+            .any(|sub_msg| matches!(sub_msg.msg, CosmosMsg::Custom(_))));
     }
 }
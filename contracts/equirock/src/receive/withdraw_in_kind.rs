@@ -0,0 +1,96 @@
+use astroport::asset::AssetInfo;
+use cosmwasm_std::{
+    to_binary, Addr, CosmosMsg, Decimal, DepsMut, Env, Response, StdResult, Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
+
+use crate::{
+    fees::accrue_management_fee,
+    helpers::{payout_msg, quote_asset, subaccount_withdraw_msgs, transfer_msg},
+    querier::{query_holdings, query_token_info},
+    state::{BASKET, CONFIG},
+};
+
+/// Burns the LP tokens and sends the sender their share of every basket asset and of the
+/// deposit asset as is. It places no orders and needs no prices, so it works even when the
+/// markets or the oracle are down.
+pub fn withdraw_in_kind(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    sender: String,
+    amount: Uint128,
+) -> StdResult<Response<InjectiveMsgWrapper>> {
+    let config = CONFIG.load(deps.storage)?;
+    let basket = BASKET.load(deps.storage)?;
+
+    let sender = deps.api.addr_validate(&sender)?;
+
+    let total_supply = query_token_info(&deps.querier, &config.lp_token)?.total_supply;
+
+    // The withdrawn share is diluted by the fee minted in the same transaction
+    let (management_fee, fee_messages) =
+        accrue_management_fee(deps.storage, &env, &config, total_supply)?;
+    let total_supply = total_supply.checked_add(management_fee)?;
+
+    let withdraw_ratio = Decimal::from_ratio(amount, total_supply);
+
+    let mut messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![WasmMsg::Execute {
+        contract_addr: config.lp_token.to_string(),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Burn { amount })?,
+    }
+    .into()];
+    messages.extend(fee_messages);
+
+    let quote_asset = quote_asset(&config)?;
+    let asset_infos = basket
+        .assets
+        .iter()
+        .map(|basket_asset| basket_asset.asset.info.to_owned())
+        .chain(std::iter::once(quote_asset.to_owned()));
+
+    // The deposit asset is paid out as the deposit asset, a cw20 one redeemed from its bank denom
+    let pay = |info: &AssetInfo, recipient: &Addr, amount: Uint128| {
+        if *info == quote_asset {
+            payout_msg(&config, recipient, amount)
+        } else {
+            transfer_msg(info, recipient, amount)
+        }
+    };
+
+    let mut payouts: Vec<(AssetInfo, Uint128)> = vec![];
+    let mut payout_messages: Vec<CosmosMsg<InjectiveMsgWrapper>> = vec![];
+    for info in asset_infos {
+        let balance = query_holdings(&deps.querier, &info, &env.contract.address)?;
+        let amount = balance * withdraw_ratio;
+        payouts.push((info.to_owned(), amount));
+
+        let exit_fee = config.fees.exit_fee(&sender, amount);
+        let amount = amount.checked_sub(exit_fee)?;
+
+        if !exit_fee.is_zero() {
+            payout_messages.push(pay(&info, &config.fees.collector, exit_fee)?);
+        }
+
+        if !amount.is_zero() {
+            payout_messages.push(pay(&info, &sender, amount)?);
+        }
+    }
+
+    // The subaccount makes up for what the bank balance lacks before the payouts
+    messages.extend(subaccount_withdraw_msgs(
+        &deps.querier,
+        &env.contract.address,
+        &basket,
+        &payouts,
+    )?);
+    messages.extend(payout_messages);
+
+    Ok(Response::new()
+        .add_attributes(vec![
+            ("method", "withdraw_in_kind".to_string()),
+            ("amount", amount.to_string()),
+        ])
+        .add_messages(messages))
+}
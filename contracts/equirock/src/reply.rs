@@ -1,20 +1,37 @@
 use std::str::FromStr;
 
-use cosmwasm_std::{DepsMut, Env, Reply, Response, StdError, StdResult};
+use cosmwasm_std::{
+    CosmosMsg, DepsMut, Env, Reply, ReplyOn, Response, StdError, StdResult, SubMsg,
+};
 use injective_cosmwasm::InjectiveMsgWrapper;
 use injective_math::FPDecimal;
 use protobuf::Message;
 
 use crate::{
+    ledger::complete_order,
     response::MsgInstantiateContractResponse,
-    state::{ClobCache, CLOB_CACHE, CONFIG},
+    state::{FillStatus, OrderFailurePolicy, BASKET, CONFIG},
     ContractError,
 };
 
 use injective_protobuf::proto::tx;
 
 pub const INSTANTIATE_REPLY_ID: u64 = 1;
-pub const ATOMIC_ORDER_REPLY_ID: u64 = 2;
+/// Reply ids of the orders, offset by the index of the traded asset in the basket.
+pub const ORDER_REPLY_ID_OFFSET: u64 = 1_000;
+
+/// Order on the market of the basket asset at `asset_index`, replying on success and failure.
+pub fn order_submsg(
+    msg: CosmosMsg<InjectiveMsgWrapper>,
+    asset_index: usize,
+) -> SubMsg<InjectiveMsgWrapper> {
+    SubMsg {
+        id: ORDER_REPLY_ID_OFFSET + asset_index as u64,
+        msg,
+        gas_limit: None,
+        reply_on: ReplyOn::Always,
+    }
+}
 
 pub fn handle_lp_init(
     deps: DepsMut,
@@ -43,10 +60,31 @@ pub fn handle_order(
 ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     let dec_scale_factor: FPDecimal = FPDecimal::from(1_000_000_000_000_000_000_i128);
     let id = msg.id;
+    let asset = BASKET
+        .load(deps.storage)?
+        .assets
+        .get((id - ORDER_REPLY_ID_OFFSET) as usize)
+        .map(|basket_asset| basket_asset.asset.info.to_owned())
+        .ok_or(ContractError::UnrecognisedReply(id))?;
+
+    let result = match msg.result.into_result() {
+        Ok(result) => result,
+        Err(err) => {
+            if CONFIG.load(deps.storage)?.order_failure == OrderFailurePolicy::Abort {
+                return Err(ContractError::SubMsgFailure(err));
+            }
+
+            let zero = FPDecimal::zero();
+            complete_order(deps.storage, &asset, zero, zero, zero, FillStatus::Failed)?;
+
+            return Ok(Response::new()
+                .add_attribute("failed_asset", asset.to_string())
+                .add_attribute("error", err));
+        }
+    };
+
     let order_response: tx::MsgCreateSpotMarketOrderResponse = Message::parse_from_bytes(
-        msg.result
-            .into_result()
-            .map_err(ContractError::SubMsgFailure)?
+        result
             .data
             .ok_or_else(|| ContractError::ReplyParseFailure {
                 id,
@@ -69,17 +107,18 @@ pub fn handle_order(
     let price = FPDecimal::from_str(&trade_data.price)? / dec_scale_factor;
     let fee = FPDecimal::from_str(&trade_data.fee)? / dec_scale_factor;
 
-    CLOB_CACHE.update::<_, StdError>(deps.storage, |mut clob_cache| {
-        clob_cache.push(ClobCache {
-            quantity,
-            price,
-            fee,
-        });
-
-        Ok(clob_cache)
-    })?;
+    let fill = complete_order(
+        deps.storage,
+        &asset,
+        quantity,
+        price,
+        fee,
+        FillStatus::Filled,
+    )?;
 
     Ok(Response::new()
+        .add_attribute("asset", asset.to_string())
+        .add_attribute("market_id", fill.market_id.as_str())
         .add_attribute("quantity", quantity.to_string())
         .add_attribute("fee", fee.to_string())
         .add_attribute("price", price.to_string()))
@@ -0,0 +1,134 @@
+use cosmwasm_std::{Decimal, StdError, StdResult};
+
+use crate::{
+    fees::BPS_DENOMINATOR,
+    state::{BasketAsset, SlippageConfig},
+};
+
+/// Maximum slippage of a market order, 50% away from the oracle price.
+pub const MAX_SLIPPAGE_BPS: u64 = 5_000;
+
+impl SlippageConfig {
+    pub fn validate(&self) -> StdResult<()> {
+        if [
+            self.deposit_bps,
+            self.withdraw_bps,
+            self.rebalance_bps,
+            self.max_price_impact_bps.unwrap_or_default(),
+        ]
+        .iter()
+        .any(|bps| *bps > MAX_SLIPPAGE_BPS)
+        {
+            return Err(StdError::generic_err(format!(
+                "Slippage cannot exceed {} bps",
+                MAX_SLIPPAGE_BPS
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl BasketAsset {
+    /// Slippage config of the asset market, falling back to the vault one.
+    pub fn slippage<'a>(&'a self, default: &'a SlippageConfig) -> &'a SlippageConfig {
+        self.slippage.as_ref().unwrap_or(default)
+    }
+}
+
+/// Checks that the slippage requested by a caller is no looser than the one configured for the
+/// asset market it trades on.
+pub fn check_max_slippage(max_slippage_bps: Option<u64>, configured_bps: u64) -> StdResult<()> {
+    match max_slippage_bps {
+        Some(max_slippage_bps) if max_slippage_bps > configured_bps => {
+            Err(StdError::generic_err(format!(
+                "Slippage cannot exceed the configured {} bps",
+                configured_bps
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Limit price multiplier of a buy order, above one.
+pub fn buy_slippage(slippage_bps: u64) -> StdResult<Decimal> {
+    Ok(Decimal::one().checked_add(Decimal::from_ratio(slippage_bps, BPS_DENOMINATOR))?)
+}
+
+/// Limit price multiplier of a sell order, below one.
+pub fn sell_slippage(slippage_bps: u64) -> StdResult<Decimal> {
+    Ok(Decimal::one().checked_sub(Decimal::from_ratio(slippage_bps, BPS_DENOMINATOR))?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use astroport::asset::{Asset, AssetInfo};
+    use cosmwasm_std::{Decimal, Uint128};
+    use injective_cosmwasm::MarketId;
+    use pyth_sdk_cw::PriceIdentifier;
+
+    use super::{buy_slippage, check_max_slippage, sell_slippage};
+    use crate::{
+        state::{BasketAsset, SlippageConfig},
+        tests::{INJUSDT_MARKET_ID, PRICE_ID_INJ},
+    };
+
+    #[test]
+    fn slippage_multipliers() {
+        assert_eq!(
+            buy_slippage(500).unwrap(),
+            Decimal::from_str("1.05").unwrap()
+        );
+        assert_eq!(
+            sell_slippage(500).unwrap(),
+            Decimal::from_str("0.95").unwrap()
+        );
+    }
+
+    #[test]
+    fn slippage_per_asset() {
+        let config = SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        };
+        let mut basket_asset = BasketAsset {
+            asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: String::from("inj"),
+                },
+                amount: Uint128::zero(),
+            },
+            weight: Uint128::one(),
+            pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+            spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+            slippage: None,
+            drift_band_bps: None,
+            oracle: None,
+        };
+        assert_eq!(basket_asset.slippage(&config), &config);
+
+        let asset_config = SlippageConfig {
+            deposit_bps: 1_000,
+            withdraw_bps: 1_000,
+            rebalance_bps: 2_000,
+            max_price_impact_bps: None,
+        };
+        basket_asset.slippage = Some(asset_config.to_owned());
+        assert_eq!(basket_asset.slippage(&config), &asset_config);
+    }
+
+    #[test]
+    fn max_slippage() {
+        check_max_slippage(None, 500).unwrap();
+        check_max_slippage(Some(100), 500).unwrap();
+        check_max_slippage(Some(500), 500).unwrap();
+        assert_eq!(
+            check_max_slippage(Some(501), 500).unwrap_err().to_string(),
+            "Generic error: Slippage cannot exceed the configured 500 bps"
+        );
+    }
+}
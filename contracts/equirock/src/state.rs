@@ -5,47 +5,326 @@ use pyth_sdk_cw::PriceIdentifier;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct Config {
+    pub owner: Addr,
     pub lp_token: Addr,
     pub deposit_asset: AssetInfo,
+    /// Injective cw20 adapter converting a cw20 deposit asset to the bank denom its markets are
+    /// quoted in.
+    pub cw20_adapter: Option<Addr>,
     pub pyth_contract_addr: Addr,
+    /// Address allowed to pause operations in an emergency.
+    pub guardian: Option<Addr>,
+    /// Seconds between queuing a config or basket change and the moment it can be applied.
+    pub timelock_delay: u64,
+    pub fees: FeeConfig,
+    pub slippage: SlippageConfig,
+    pub keeper: KeeperConfig,
+    /// Splits the rebalance sales into slices executed over several calls. Sold at once if unset.
+    pub twap: Option<TwapConfig>,
+    pub rebalance_mode: RebalanceMode,
+    pub order_failure: OrderFailurePolicy,
+    pub oracle: OracleConfig,
+    pub pricing: PricingConfig,
 }
 
 pub const CONFIG: Item<Config> = Item::new("config");
 
+/// Fees charged to the LP token holders, in basis points, and the address collecting them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeConfig {
+    pub collector: Addr,
+    /// Annual fee, accrued pro rata over time by minting LP tokens to the collector.
+    pub management_fee_bps: u64,
+    /// Share of the gains above the high-water mark, charged by minting LP tokens to the
+    /// collector.
+    pub performance_fee_bps: u64,
+    /// Minimum number of seconds between two performance fee crystallizations.
+    pub performance_fee_period: u64,
+    /// Share of the deposit, paid in the deposited assets to the collector.
+    pub entry_fee_bps: u64,
+    /// Share of the withdrawal, paid in the deposit asset to the collector.
+    pub exit_fee_bps: u64,
+    /// Addresses paying no entry and exit fees, e.g. market makers creating and redeeming.
+    pub fee_exempt: Vec<Addr>,
+}
+
+/// Maximum deviation from the oracle price accepted by the market orders of each operation, in
+/// basis points.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SlippageConfig {
+    pub deposit_bps: u64,
+    pub withdraw_bps: u64,
+    pub rebalance_bps: u64,
+    /// Maximum distance between the average fill price estimated from the orderbook and the
+    /// best price. Larger orders are shrunk, or rejected for withdrawals. Unchecked if unset.
+    pub max_price_impact_bps: Option<u64>,
+}
+
+/// Limits on the Pyth prices used by the deposits, withdrawals and rebalances.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct OracleConfig {
+    /// Maximum age of the current and EMA prices, in seconds.
+    pub max_age: u64,
+    /// Maximum confidence interval of the current price, in basis points of the price.
+    pub max_conf_bps: u64,
+    /// Maximum gap between the oracle price and the mid price of the market an order is sent
+    /// to, in basis points of the oracle price. Unchecked if unset.
+    pub max_deviation_bps: Option<u64>,
+}
+
+/// Price valuing the basket assets in each operation.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PricingConfig {
+    pub deposit: PricingMode,
+    pub withdraw: PricingMode,
+    pub rebalance: PricingMode,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingMode {
+    /// Current Pyth price.
+    Spot,
+    /// Pyth EMA price, which short-lived spikes barely move.
+    Ema,
+    /// Higher or lower of the current and EMA prices, widened by its confidence interval,
+    /// whichever protects the existing holders. Rebalances are valued at the EMA price, and order
+    /// limits use the upper bound for sells and the lower one for buys.
+    Conservative,
+}
+
+/// Who can trigger a rebalance, how often, and what they are paid for it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct KeeperConfig {
+    /// Minimum number of seconds between two rebalances.
+    pub min_interval: u64,
+    /// Addresses allowed to rebalance besides the owner. Anyone can if empty.
+    pub keepers: Vec<Addr>,
+    /// Paid to the caller in the deposit asset, out of the proceeds of the rebalance sales.
+    pub bounty: Uint128,
+}
+
+/// How the rebalance trades.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RebalanceMode {
+    /// Atomic market orders, settled within the rebalance transaction.
+    Atomic,
+    /// Post-only limit orders placed `offset_bps` away from the oracle price, on the maker side.
+    /// Every rebalance cancels and replaces the open orders, and withdraws the fills from the
+    /// contract subaccount.
+    Passive { offset_bps: u64 },
+}
+
+/// What happens when the order of one basket asset fails during a deposit or a rebalance.
+/// Withdrawals always revert, the user can exit in kind instead.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderFailurePolicy {
+    /// Reverts the whole transaction.
+    Abort,
+    /// Goes on with the other assets. The unspent part of a deposit is refunded.
+    Refund,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct TwapConfig {
+    /// Number of slices of each rebalance, one selling everything at once.
+    pub slices: u64,
+    /// Minimum number of seconds between two slices.
+    pub slice_interval: u64,
+}
+
+/// Sales left from a rebalance split into slices.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RebalancePlan {
+    /// Quantity sold by each slice, per asset. The buys of a slice aim at the basket value when it
+    /// runs.
+    pub sells: Vec<PlannedSell>,
+    pub slices_left: u64,
+    pub slice_interval: u64,
+    pub next_slice_at: Timestamp,
+    /// Share of the keeper bounty paid for each slice, so the plan pays it once in total.
+    #[serde(default)]
+    pub slice_bounty: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PlannedSell {
+    pub asset: AssetInfo,
+    pub quantity: Decimal,
+}
+
+pub const REBALANCE_PLAN: Item<RebalancePlan> = Item::new("rebalance-plan");
+
+/// Last time the basket was rebalanced.
+pub const LAST_REBALANCE: Item<Timestamp> = Item::new("last-rebalance");
+
+/// Last time the management fee was accrued.
+pub const LAST_FEE_ACCRUAL: Item<Timestamp> = Item::new("last-fee-accrual");
+
+/// Highest NAV per share, in USDT, on which the performance fee was charged.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct HighWaterMark {
+    pub nav_per_share: Decimal,
+    pub crystallized_at: Timestamp,
+}
+
+pub const HIGH_WATER_MARK: Item<HighWaterMark> = Item::new("high-water-mark");
+
+/// Pending transfer of the contract ownership, claimable by `owner` until `ttl` (seconds).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct OwnershipProposal {
+    pub owner: Addr,
+    pub ttl: u64,
+}
+
+pub const OWNERSHIP_PROPOSAL: Item<OwnershipProposal> = Item::new("ownership-proposal");
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct BasketAsset {
     pub asset: Asset,
     pub weight: Uint128,
     pub pyth_price_feed: PriceIdentifier,
     pub spot_market_id: MarketId,
+    /// Replaces the vault slippage config for the asset market, e.g. a less liquid one.
+    pub slippage: Option<SlippageConfig>,
+    /// Replaces the basket drift band for the asset.
+    pub drift_band_bps: Option<u64>,
+    /// Replaces the vault oracle limits for the asset price feed.
+    pub oracle: Option<OracleConfig>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Basket {
     pub assets: Vec<BasketAsset>,
+    /// Distance between the current and the target weight of an asset, in basis points of the
+    /// basket value, within which the rebalance leaves the asset alone.
+    #[serde(default)]
+    pub drift_band_bps: u64,
+}
+
+/// Operations stopped by the guardian or the owner. While withdrawals are paused, users can
+/// still exit, but the basket assets are paid out in kind instead of being sold.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct PauseState {
+    pub deposit: bool,
+    pub withdraw: bool,
+    pub rebalance: bool,
+}
+
+pub const PAUSE_STATE: Item<PauseState> = Item::new("pause-state");
+
+pub const BASKET: Item<Basket> = Item::new("basket");
+
+/// Config fields replaced by a queued `UpdateConfig`, with the addresses already validated.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct ConfigUpdate {
+    pub pyth_contract_addr: Option<Addr>,
+    pub deposit_asset: Option<AssetInfo>,
+    pub cw20_adapter: Option<Addr>,
+    pub guardian: Option<Addr>,
+    #[serde(default)]
+    pub clear_guardian: bool,
+    pub timelock_delay: Option<u64>,
+    pub fees: Option<FeeConfig>,
+    pub slippage: Option<SlippageConfig>,
+    pub keeper: Option<KeeperConfig>,
+    pub twap: Option<TwapConfig>,
+    pub rebalance_mode: Option<RebalanceMode>,
+    pub order_failure: Option<OrderFailurePolicy>,
+    pub oracle: Option<OracleConfig>,
+    pub pricing: Option<PricingConfig>,
+}
+
+/// Config or basket change waiting for the timelock to pass.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigChange {
+    UpdateConfig(ConfigUpdate),
+    UpdateAssetWeight {
+        asset: AssetInfo,
+        weight: Uint128,
+    },
+    AddAsset {
+        basket_asset: BasketAsset,
+    },
+    RetireAsset {
+        asset: AssetInfo,
+    },
+    UpdateDriftBand {
+        asset: Option<AssetInfo>,
+        drift_band_bps: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingChange {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub effective_at: Timestamp,
+}
+
+pub const PENDING_CHANGES: Map<u64, PendingChange> = Map::new("pending-changes");
+pub const PENDING_CHANGE_ID: Item<u64> = Item::new("pending-change-id");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Deposit,
+    Withdraw,
+    Rebalance,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSide {
+    Buy,
+    Sell,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FillStatus {
+    /// Order sent, waiting for its reply.
+    Pending,
+    Filled,
+    /// Order which failed, with nothing traded.
+    Failed,
+    /// Post-only order left on the book, whose later fills the operation does not follow.
+    Resting,
+}
+
+/// Execution of one market order of an operation. Quantity, price and fee are in chain units, i.e.
+/// base denom atomics, quote atomics per base atomic and quote atomics.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct ClobCache {
+pub struct Fill {
+    pub asset: AssetInfo,
+    pub market_id: MarketId,
+    pub side: OrderSide,
     pub quantity: FPDecimal,
     pub price: FPDecimal,
     pub fee: FPDecimal,
+    pub status: FillStatus,
 }
 
-impl ClobCache {
-    pub fn new() -> Self {
-        ClobCache {
-            quantity: FPDecimal::zero(),
-            price: FPDecimal::zero(),
-            fee: FPDecimal::zero(),
-        }
-    }
+/// Deposit, withdrawal or rebalance, with the fills of its orders.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Operation {
+    pub id: u64,
+    pub kind: OperationKind,
+    pub initiator: Addr,
+    pub started_at: Timestamp,
+    pub fills: Vec<Fill>,
 }
 
-pub const BASKET: Item<Basket> = Item::new("basket");
-pub const CLOB_CACHE: Item<Vec<ClobCache>> = Item::new("clob-cache");
+/// The last operations, the oldest ones being pruned.
+pub const OPERATIONS: Map<u64, Operation> = Map::new("operations");
+pub const OPERATION_ID: Item<u64> = Item::new("operation-id");
+/// Operation waiting for the replies of its orders and for its callback.
+pub const OPERATION_IN_PROGRESS: Item<u64> = Item::new("operation-in-progress");
@@ -3,22 +3,35 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use crate::contract::{execute, instantiate, query};
+use crate::fees::SECONDS_PER_YEAR;
 use crate::helpers::get_message_data;
-use crate::msg::{ExecuteMsg, GetBasketAssetIdealRatioResponse, InstantiateMsg, QueryMsg};
-use crate::state::{Basket, BasketAsset, Config, CONFIG};
+use crate::msg::{
+    AssetWeightResponse, CallbackMsg, ExecuteMsg, FeeConfigMsg, FeedHealthResponse, FeedStatus,
+    GetBasketAssetIdealRatioResponse, InstantiateMsg, KeeperConfigMsg, PendingFeesResponse,
+    QueryMsg, UpdateConfigMsg,
+};
+use crate::state::{
+    Basket, BasketAsset, Config, FeeConfig, FillStatus, KeeperConfig, Operation, OperationKind,
+    OracleConfig, OrderFailurePolicy, OrderSide, PauseState, PendingChange, PlannedSell,
+    PricingConfig, PricingMode, RebalanceMode, RebalancePlan, SlippageConfig, TwapConfig, BASKET,
+    CONFIG, OPERATION_IN_PROGRESS, PENDING_CHANGES, REBALANCE_PLAN,
+};
 use crate::ContractError;
 
 use astroport::asset::{Asset, AssetInfo};
 use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage};
 use cosmwasm_std::{
-    coin, coins, from_binary, to_binary, Addr, BalanceResponse, Coin, ContractResult, Decimal, Env,
-    OwnedDeps, QuerierResult, SystemError, SystemResult, Timestamp, Uint128, WasmQuery,
+    coin, coins, from_binary, to_binary, Addr, BalanceResponse, BankMsg, Coin, ContractResult,
+    CosmosMsg, Decimal, Env, OwnedDeps, QuerierResult, ReplyOn, Response, StdResult, SystemError,
+    SystemResult, Timestamp, Uint128, WasmMsg, WasmQuery,
 };
-use cw20::TokenInfoResponse;
+use cw20::{BalanceResponse as Cw20BalanceResponse, TokenInfoResponse};
 use injective_cosmwasm::{
-    mock_dependencies, DenomDecimals, HandlesBankBalanceQuery, HandlesDenomDecimalsQuery,
-    HandlesMarketIdQuery, HandlesSmartQuery, InjectiveMsg, InjectiveQueryWrapper, MarketId,
-    QueryDenomDecimalsResponse, SpotMarket, SpotMarketResponse, WasmMockQuerier,
+    mock_dependencies, DenomDecimals, Deposit, HandlesBankBalanceQuery, HandlesDenomDecimalsQuery,
+    HandlesMarketIdQuery, HandlesSmartQuery, HandlesSubaccountAndDenomQuery, InjectiveMsg,
+    InjectiveMsgWrapper, InjectiveQueryWrapper, MarketId, MarketMidPriceAndTOBResponse, OrderType,
+    QueryDenomDecimalsResponse, SpotMarket, SpotMarketResponse, SubaccountDepositResponse,
+    SubaccountId, WasmMockQuerier,
 };
 use injective_math::FPDecimal;
 use pyth_sdk_cw::testing::MockPyth;
@@ -29,6 +42,10 @@ pub const PRICE_ID_INJ: &str = "2d9315a88f3019f8efa88dfe9c0f0843712da0bac814461e
 pub const PRICE_ID_ATOM: &str = "61226d39beea19d334f17c2febce27e12646d84675924ebb02b9cdaea68727e3";
 
 pub const LP_TOKEN_ADDR: &str = "lp-token-0001";
+/// Cw20 deposit asset, of which the contract holds `USDT_TOKEN_BALANCE`.
+pub const USDT_TOKEN_ADDR: &str = "usdt-token";
+pub const USDT_TOKEN_BALANCE: u128 = 1_000;
+pub const CW20_ADAPTER_ADDR: &str = "cw20-adapter";
 pub const USDT: &str = "peggy0xdAC17F958D2ee523a2206206994597C13D831ec7";
 pub const ATOM: &str = "factory/inj17vytdwqczqz72j65saukplrktd4gyfme5agf6c/atom";
 
@@ -46,6 +63,8 @@ pub fn inj_mock_deps(
     custom_querier.smart_query_handler = Some(Box::new(create_smart_query_handler(mock_pyth)));
     custom_querier.spot_market_response_handler = Some(Box::new(create_spot_market_handler()));
     custom_querier.denom_decimals_handler = Some(Box::new(create_denom_decimals_handler()));
+    custom_querier.subaccount_deposit_response_handler =
+        Some(Box::new(create_subaccount_deposit_handler(|_| 0)));
     OwnedDeps {
         api: MockApi::default(),
         storage: MockStorage::default(),
@@ -76,6 +95,15 @@ fn create_smart_query_handler(mock_pyth: &MockPyth) -> impl HandlesSmartQuery {
                 );
             }
 
+            if contract_addr == USDT_TOKEN_ADDR {
+                return SystemResult::Ok(
+                    to_binary(&Cw20BalanceResponse {
+                        balance: Uint128::new(USDT_TOKEN_BALANCE),
+                    })
+                    .into(),
+                );
+            }
+
             SystemResult::Err(SystemError::NoSuchContract {
                 addr: contract_addr.to_owned(),
             })
@@ -157,7 +185,7 @@ fn create_denom_decimals_handler() -> impl HandlesDenomDecimalsQuery {
     Temp {}
 }
 
-fn create_balance_bank_handler(
+pub fn create_balance_bank_handler(
     get_amount: Option<fn(&str) -> u128>,
 ) -> impl HandlesBankBalanceQuery {
     struct Temp {
@@ -182,6 +210,58 @@ fn create_balance_bank_handler(
     Temp { get_amount }
 }
 
+/// Total and available deposits of the contract subaccount, in chain units.
+pub fn create_subaccount_deposit_handler(
+    get_amount: fn(&str) -> u128,
+) -> impl HandlesSubaccountAndDenomQuery {
+    struct Temp {
+        get_amount: fn(&str) -> u128,
+    }
+    impl HandlesSubaccountAndDenomQuery for Temp {
+        fn handle(&self, _subaccount_id: SubaccountId, denom: String) -> QuerierResult {
+            let amount = FPDecimal::from((self.get_amount)(&denom));
+            let response = SubaccountDepositResponse {
+                deposits: Deposit {
+                    available_balance: amount,
+                    total_balance: amount,
+                },
+            };
+            SystemResult::Ok(ContractResult::from(to_binary(&response)))
+        }
+    }
+    Temp { get_amount }
+}
+
+/// Mid prices of the INJ/USDT and ATOM/USDT markets, in chain units.
+pub fn create_mid_price_handler(
+    inj_mid_price: &str,
+    atom_mid_price: &str,
+) -> impl HandlesMarketIdQuery {
+    struct Temp {
+        inj_mid_price: FPDecimal,
+        atom_mid_price: FPDecimal,
+    }
+    impl HandlesMarketIdQuery for Temp {
+        fn handle(&self, market_id: MarketId) -> QuerierResult {
+            let mid_price = if market_id.as_str() == ATOMUSDT_MARKET_ID {
+                self.atom_mid_price
+            } else {
+                self.inj_mid_price
+            };
+            let response = MarketMidPriceAndTOBResponse {
+                mid_price: Some(mid_price),
+                best_bid: None,
+                best_ask: None,
+            };
+            SystemResult::Ok(ContractResult::from(to_binary(&response)))
+        }
+    }
+    Temp {
+        inj_mid_price: FPDecimal::from_str(inj_mid_price).unwrap(),
+        atom_mid_price: FPDecimal::from_str(atom_mid_price).unwrap(),
+    }
+}
+
 pub fn setup_test(
     mock_pyth: &MockPyth,
     block_timestamp: UnixTimestamp,
@@ -203,6 +283,75 @@ pub fn setup_test(
     (dependencies, env)
 }
 
+pub fn apply_pending_change(
+    deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier, InjectiveQueryWrapper>,
+    env: &Env,
+    id: u64,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ApplyChange { id },
+    )
+}
+
+/// Ends the operation in progress, as its last callback would.
+pub fn end_operation(
+    deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier, InjectiveQueryWrapper>,
+) {
+    OPERATION_IN_PROGRESS.remove(&mut deps.storage);
+}
+
+/// Config of the tests saving it directly instead of instantiating the contract. Tests override
+/// the fields they need with the struct update syntax.
+pub fn mock_config() -> Config {
+    Config {
+        owner: Addr::unchecked("owner"),
+        lp_token: Addr::unchecked(LP_TOKEN_ADDR),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: USDT.to_owned(),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: Addr::unchecked(PYTH_CONTRACT_ADDR),
+        guardian: None,
+        timelock_delay: 0,
+        fees: FeeConfig {
+            collector: Addr::unchecked("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfig {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    }
+}
+
 fn _handle_wasm_query(pyth: &MockPyth, wasm_query: &WasmQuery) -> QuerierResult {
     match wasm_query {
         WasmQuery::Smart { contract_addr, msg } if *contract_addr == PYTH_CONTRACT_ADDR => {
@@ -247,8 +396,47 @@ fn proper_initialization() {
         deposit_asset: AssetInfo::NativeToken {
             denom: String::from("usdt"),
         },
-        pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
-        basket: Basket { assets: vec![] },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
     };
     let info = mock_info("creator", &coins(1000, "earth"));
 
@@ -257,7 +445,8 @@ fn proper_initialization() {
     assert_eq!(1, res.messages.len());
 
     let res = query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {}).unwrap();
-    let _value: Config = from_binary(&res).unwrap();
+    let value: Config = from_binary(&res).unwrap();
+    assert_eq!(value.owner, Addr::unchecked("creator"));
 }
 
 #[test]
@@ -271,8 +460,47 @@ fn deposit_incorrect_denom() {
         deposit_asset: AssetInfo::NativeToken {
             denom: String::from("usdt"),
         },
-        pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
-        basket: Basket { assets: vec![] },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
     };
     let info = mock_info("creator", &vec![]);
 
@@ -286,6 +514,9 @@ fn deposit_incorrect_denom() {
                 denom: String::from("not-usdt"),
             },
         },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
     };
 
     execute(deps.as_mut(), mock_env(), auth_info, msg).unwrap();
@@ -302,8 +533,47 @@ fn deposit_different_than_in_asset() {
         deposit_asset: AssetInfo::NativeToken {
             denom: String::from("usdt"),
         },
-        pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
-        basket: Basket { assets: vec![] },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
     };
     let info = mock_info("creator", &vec![]);
 
@@ -317,6 +587,9 @@ fn deposit_different_than_in_asset() {
                 denom: String::from("usdt"),
             },
         },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
     };
 
     execute(deps.as_mut(), mock_env(), auth_info, msg).unwrap();
@@ -334,8 +607,47 @@ fn deposit_correct_denom() {
         deposit_asset: AssetInfo::NativeToken {
             denom: USDT.to_owned(),
         },
-        pyth_contract_addr: Addr::unchecked("pyth-contract-addr"),
-        basket: Basket { assets: vec![] },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
     };
     let info = mock_info("creator", &vec![]);
 
@@ -360,6 +672,9 @@ fn deposit_correct_denom() {
                 denom: USDT.to_owned(),
             },
         },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
     };
 
     let _res = execute(deps.as_mut(), env.to_owned(), auth_info, msg).unwrap();
@@ -409,9 +724,10 @@ fn query_basket_prices() {
         etf_token_code_id: 1,
         etf_token_name: String::from("ER-Strategy-1"),
         deposit_asset: AssetInfo::NativeToken {
-            denom: String::from("usdt"),
+            denom: USDT.to_owned(),
         },
-        pyth_contract_addr: Addr::unchecked(PYTH_CONTRACT_ADDR),
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
         basket: Basket {
             assets: vec![
                 BasketAsset {
@@ -426,6 +742,9 @@ fn query_basket_prices() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
                 BasketAsset {
                     asset: Asset {
@@ -439,12 +758,90 @@ fn query_basket_prices() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
             ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
         },
     };
     let info = mock_info("creator", &vec![]);
 
+    // The orders are paid in the market quote denom, the bank denom of a cw20 deposit asset
+    let err = instantiate(
+        deps.as_mut(),
+        env.to_owned(),
+        info.clone(),
+        InstantiateMsg {
+            deposit_asset: AssetInfo::Token {
+                contract_addr: Addr::unchecked("stablecoin"),
+            },
+            ..msg.clone()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Generic error: Cw20 deposit asset stablecoin requires a cw20 adapter"
+    );
+
+    let err = instantiate(
+        deps.as_mut(),
+        env.to_owned(),
+        info.clone(),
+        InstantiateMsg {
+            deposit_asset: AssetInfo::Token {
+                contract_addr: Addr::unchecked("stablecoin"),
+            },
+            cw20_adapter: Some(String::from(CW20_ADAPTER_ADDR)),
+            ..msg.clone()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Generic error: Spot market {} is quoted in {} instead of the deposit asset stablecoin",
+            INJUSDT_MARKET_ID, USDT
+        )
+    );
+
     let res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
 
     assert_eq!(1, res.messages.len());
@@ -512,7 +909,8 @@ fn deposit() {
         deposit_asset: AssetInfo::NativeToken {
             denom: String::from(USDT),
         },
-        pyth_contract_addr: Addr::unchecked(PYTH_CONTRACT_ADDR),
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
         basket: Basket {
             assets: vec![
                 BasketAsset {
@@ -527,6 +925,9 @@ fn deposit() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
                 BasketAsset {
                     asset: Asset {
@@ -540,8 +941,47 @@ fn deposit() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
             ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
         },
     };
     let info = mock_info("creator", &vec![]);
@@ -573,7 +1013,12 @@ fn deposit() {
         }],
     );
 
-    let msg = ExecuteMsg::Deposit { asset: asset };
+    let msg = ExecuteMsg::Deposit {
+        asset,
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
+    };
 
     let res = execute(deps.as_mut(), env.to_owned(), info, msg).unwrap();
     let messages = res.messages;
@@ -581,6 +1026,15 @@ fn deposit() {
     // res.messages.len()
     assert_eq!(messages.len(), 3);
 
+    // Replies identify the basket asset of each order
+    assert_eq!(
+        messages[..2]
+            .iter()
+            .map(|m| (m.id, m.reply_on.to_owned()))
+            .collect::<Vec<_>>(),
+        vec![(1_000, ReplyOn::Always), (1_001, ReplyOn::Always)]
+    );
+
     if let InjectiveMsg::CreateSpotMarketOrder { sender, order } =
         &get_message_data(&messages, 0).msg_data
     {
@@ -608,10 +1062,97 @@ fn deposit() {
     } else {
         panic!("Wrong message type!");
     }
+
+    // Both orders are waiting for their reply in the ledger
+    let operations: Vec<Operation> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.to_owned(),
+            QueryMsg::GetOperations {
+                start_before: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].kind, OperationKind::Deposit);
+    assert_eq!(operations[0].initiator, Addr::unchecked("creator"));
+    assert_eq!(
+        operations[0]
+            .fills
+            .iter()
+            .map(|f| (f.market_id.as_str(), f.side, f.status))
+            .collect::<Vec<_>>(),
+        vec![
+            (INJUSDT_MARKET_ID, OrderSide::Buy, FillStatus::Pending),
+            (ATOMUSDT_MARKET_ID, OrderSide::Buy, FillStatus::Pending),
+        ]
+    );
+
+    // Nothing else runs until the deposit callback
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Operation 1 is in progress");
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info(CONTRACT_ADDR, &[]),
+        ExecuteMsg::Callback(CallbackMsg::AfterWithdraw {
+            sender: Addr::unchecked("creator"),
+            min_received: None,
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Generic error: Operation 1 is a Deposit, not a Withdraw"
+    );
+
+    // The requested slippage is checked against the INJ market override
+    end_operation(&mut deps);
+    BASKET
+        .update(&mut deps.storage, |mut basket| -> StdResult<_> {
+            basket.assets[0].slippage = Some(SlippageConfig {
+                deposit_bps: 100,
+                ..mock_config().slippage
+            });
+            Ok(basket)
+        })
+        .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &coins(1_000_000, USDT)),
+        ExecuteMsg::Deposit {
+            asset: Asset {
+                amount: Uint128::from(1_000_000u128),
+                info: AssetInfo::NativeToken {
+                    denom: String::from(USDT),
+                },
+            },
+            min_lp_out: None,
+            deadline: None,
+            max_slippage_bps: Some(200),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Generic error: Slippage cannot exceed the configured 100 bps"
+    );
 }
 
 #[test]
-fn query_basket_value() {
+fn feed_health() {
     let current_unix_time = 10_000_000;
     let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
     let price_feed_inj = PriceFeed::new(
@@ -626,14 +1167,14 @@ fn query_basket_value() {
             price: 800000000,
             conf: 20,
             expo: -8,
-            publish_time: current_unix_time,
+            publish_time: current_unix_time - 120,
         },
     );
     let price_feed_atom = PriceFeed::new(
         PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
         Price {
             price: 1100000000,
-            conf: 20,
+            conf: 22000000,
             expo: -8,
             publish_time: current_unix_time,
         },
@@ -656,7 +1197,8 @@ fn query_basket_value() {
         deposit_asset: AssetInfo::NativeToken {
             denom: String::from(USDT),
         },
-        pyth_contract_addr: Addr::unchecked(PYTH_CONTRACT_ADDR),
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
         basket: Basket {
             assets: vec![
                 BasketAsset {
@@ -671,6 +1213,9 @@ fn query_basket_value() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
                 BasketAsset {
                     asset: Asset {
@@ -684,26 +1229,118 @@ fn query_basket_value() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
             ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
         },
     };
-    let info = mock_info("creator", &vec![]);
+    let info = mock_info("creator", &[]);
 
-    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+    instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
 
-    let res = query(deps.as_ref(), env, QueryMsg::GetBasketValueInUsdt {}).unwrap();
-    let _value: Uint128 = from_binary(&res).unwrap();
+    let res = query(deps.as_ref(), env.to_owned(), QueryMsg::GetFeedHealth {}).unwrap();
+    let health: Vec<FeedHealthResponse> = from_binary(&res).unwrap();
+    assert_eq!(
+        health
+            .iter()
+            .map(|h| (h.publish_time, h.conf_bps, h.status.to_owned()))
+            .collect::<Vec<_>>(),
+        vec![
+            (current_unix_time - 120, 1, FeedStatus::Stale),
+            (current_unix_time, 200, FeedStatus::Uncertain)
+        ]
+    );
+
+    let deposit = ExecuteMsg::Deposit {
+        asset: Asset {
+            amount: Uint128::from(1_000_000u128),
+            info: AssetInfo::NativeToken {
+                denom: String::from(USDT),
+            },
+        },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
+    };
+    let info = mock_info("user", &coins(1_000_000, USDT));
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        info.to_owned(),
+        deposit.to_owned(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Price feed of inj is stale, published at 9999880"
+    );
+
+    // A per-asset limit overrides the vault one
+    BASKET
+        .update(&mut deps.storage, |mut basket| -> StdResult<_> {
+            basket.assets[0].oracle = Some(OracleConfig {
+                max_age: 300,
+                max_conf_bps: 100,
+                max_deviation_bps: None,
+            });
+            Ok(basket)
+        })
+        .unwrap();
+
+    let err = execute(deps.as_mut(), env.to_owned(), info, deposit).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Price feed of atom is too uncertain, confidence of 200 bps"
+    );
 }
 
 #[test]
-fn rebalance() {
+fn oracle_deviation() {
     let current_unix_time = 10_000_000;
     let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
     let price_feed_inj = PriceFeed::new(
         PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
         Price {
-            price: 800000000,
+            price: 900000000,
             conf: 10,
             expo: -8,
             publish_time: current_unix_time,
@@ -718,13 +1355,13 @@ fn rebalance() {
     let price_feed_atom = PriceFeed::new(
         PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
         Price {
-            price: 1250000000,
+            price: 1100000000,
             conf: 20,
             expo: -8,
             publish_time: current_unix_time,
         },
         Price {
-            price: 1250000000,
+            price: 1100000000,
             conf: 20,
             expo: -8,
             publish_time: current_unix_time,
@@ -735,13 +1372,6 @@ fn rebalance() {
     mock_pyth.add_feed(price_feed_atom);
 
     let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
-    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
-        |denom| match denom {
-            "inj" => 1_000_000_000_000_000_000,
-            ATOM => 1_000_000,
-            _ => 1_000_000_000_000_000,
-        },
-    ))));
 
     let msg = InstantiateMsg {
         etf_token_code_id: 1,
@@ -749,7 +1379,8 @@ fn rebalance() {
         deposit_asset: AssetInfo::NativeToken {
             denom: String::from(USDT),
         },
-        pyth_contract_addr: Addr::unchecked(PYTH_CONTRACT_ADDR),
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
         basket: Basket {
             assets: vec![
                 BasketAsset {
@@ -764,12 +1395,15 @@ fn rebalance() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
                 BasketAsset {
                     asset: Asset {
                         info: {
                             AssetInfo::NativeToken {
-                                denom: String::from(ATOM),
+                                denom: String::from("atom"),
                             }
                         },
                         amount: Uint128::zero(),
@@ -777,11 +1411,50 @@ fn rebalance() {
                     pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
                     weight: Uint128::from(1u128),
                     spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
                 },
             ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: Some(100),
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
         },
     };
-    let info = mock_info("creator", &vec![]);
+    let info = mock_info("creator", &[]);
 
     let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
 
@@ -796,23 +1469,2887 @@ fn rebalance() {
         )
         .unwrap();
 
-    let asset = Asset {
-        amount: Uint128::from(1_000_000u128),
-        info: AssetInfo::NativeToken {
-            denom: String::from(USDT),
+    // 10 bps above the INJ oracle price of 9, right at the ATOM one of 11, in chain units
+    deps.querier.spot_market_mid_price_and_tob_response_handler = Some(Box::new(
+        create_mid_price_handler("0.000000000009009", "11"),
+    ));
+
+    let deposit = ExecuteMsg::Deposit {
+        asset: Asset {
+            amount: Uint128::from(1_000_000u128),
+            info: AssetInfo::NativeToken {
+                denom: String::from(USDT),
+            },
         },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
     };
-    let info = mock_info(
-        "creator",
-        &vec![Coin {
-            amount: asset.amount.to_owned(),
-            denom: String::from(USDT),
-        }],
+    let info = mock_info("user", &coins(1_000_000, USDT));
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        info.to_owned(),
+        deposit.to_owned(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.events
+            .iter()
+            .map(|e| (e.ty.as_str(), e.attributes[4].value.as_str()))
+            .collect::<Vec<_>>(),
+        vec![("oracle_deviation", "10"), ("oracle_deviation", "0")]
     );
+    end_operation(&mut deps);
 
-    let msg = ExecuteMsg::Rebalance {};
+    // Checked against the current price, not the INJ EMA price of 8 the deposit is valued at
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.pricing.deposit = PricingMode::Ema;
+                Ok(config)
+            },
+        )
+        .unwrap();
 
-    let res = execute(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        info.to_owned(),
+        deposit.to_owned(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.events
+            .iter()
+            .map(|e| (e.ty.as_str(), e.attributes[4].value.as_str()))
+            .collect::<Vec<_>>(),
+        vec![("oracle_deviation", "10"), ("oracle_deviation", "0")]
+    );
+    end_operation(&mut deps);
 
-    println!("{:?}", res);
+    // ATOM trades 2% above its oracle price
+    deps.querier.spot_market_mid_price_and_tob_response_handler = Some(Box::new(
+        create_mid_price_handler("0.000000000009009", "11.22"),
+    ));
+
+    let err = execute(deps.as_mut(), env.to_owned(), info, deposit).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Oracle price 11 of atom is 200 bps away from the mid price 11.22 of market {}",
+            ATOMUSDT_MARKET_ID
+        )
+    );
+}
+
+#[test]
+fn query_basket_value() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 900000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1100000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1100000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("atom"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &vec![]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    // 90 USDT of INJ and 110 USDT of ATOM
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 10_000_000_000_000_000_000,
+            "atom" => 10_000_000,
+            _ => 0,
+        },
+    ))));
+
+    let res = query(
+        deps.as_ref(),
+        env.to_owned(),
+        QueryMsg::GetBasketValueInUsdt {},
+    )
+    .unwrap();
+    let value: Uint128 = from_binary(&res).unwrap();
+    assert_eq!(value, Uint128::new(200_000_000));
+
+    // Half of the INJ sold by a rebalance at the oracle price, with the proceeds not spent yet
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 5_000_000_000_000_000_000,
+            "atom" => 10_000_000,
+            USDT => 45_000_000,
+            _ => 0,
+        },
+    ))));
+
+    let res = query(
+        deps.as_ref(),
+        env.to_owned(),
+        QueryMsg::GetBasketValueInUsdt {},
+    )
+    .unwrap();
+    let value: Uint128 = from_binary(&res).unwrap();
+    assert_eq!(value, Uint128::new(200_000_000));
+
+    // The proceeds and some of the ATOM held in the subaccount by resting passive orders
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 5_000_000_000_000_000_000,
+            "atom" => 4_000_000,
+            _ => 0,
+        },
+    ))));
+    deps.querier.subaccount_deposit_response_handler = Some(Box::new(
+        create_subaccount_deposit_handler(|denom| match denom {
+            "atom" => 6_000_000,
+            USDT => 45_000_000,
+            _ => 0,
+        }),
+    ));
+
+    let res = query(deps.as_ref(), env, QueryMsg::GetBasketValueInUsdt {}).unwrap();
+    let value: Uint128 = from_binary(&res).unwrap();
+    assert_eq!(value, Uint128::new(200_000_000));
+}
+
+#[test]
+fn query_tracking_error() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    // 8 USDT of INJ and 15 USDT of ATOM
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_200_000,
+            _ => 0,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from(ATOM),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), env.to_owned(), QueryMsg::GetTrackingError {}).unwrap();
+    let value: Decimal = from_binary(&res).unwrap();
+
+    // 3.5 USDT out of 23 held above the ATOM target
+    assert_eq!(value, Decimal::from_str("0.152173913043478260").unwrap());
+
+    // The USDT held counts as well, out of targets of 12.5 USDT: 2.5 above the ATOM one and
+    // 2 USDT not invested
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_200_000,
+            USDT => 2_000_000,
+            _ => 0,
+        },
+    ))));
+
+    let res = query(deps.as_ref(), env, QueryMsg::GetTrackingError {}).unwrap();
+    let value: Decimal = from_binary(&res).unwrap();
+    assert_eq!(value, Decimal::from_str("0.18").unwrap());
+}
+
+#[test]
+fn rebalance_drift_bands() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    // 8 USDT of INJ and 15 USDT of ATOM
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_200_000,
+            _ => 0,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from(ATOM),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 1_600,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR);
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    // Both weights are 15.2% away from their target
+    let res = query(deps.as_ref(), env.to_owned(), QueryMsg::GetAssetWeights {}).unwrap();
+    let weights: Vec<AssetWeightResponse> = from_binary(&res).unwrap();
+    assert!(weights.iter().all(|w| w.within_band));
+    assert_eq!(
+        weights[1].current_weight,
+        Decimal::from_str("0.652173913043478260").unwrap()
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Basket is within its drift bands");
+
+    // Narrower band for ATOM only
+    let mut basket = BASKET.load(&deps.storage).unwrap();
+    basket.assets[1].drift_band_bps = Some(1_000);
+    BASKET.save(&mut deps.storage, &basket).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    // Only ATOM is sold, followed by the rebalance callback
+    assert_eq!(res.messages.len(), 2);
+    if let InjectiveMsg::CreateSpotMarketOrder { order, .. } =
+        &get_message_data(&res.messages, 0).msg_data
+    {
+        assert_eq!(order.market_id.as_str(), ATOMUSDT_MARKET_ID);
+        assert_eq!(order.order_type, OrderType::SellAtomic);
+    } else {
+        panic!("Wrong message type!");
+    }
+}
+
+#[test]
+fn rebalance_keepers() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    // 8 USDT of INJ and 15 USDT of ATOM
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_200_000,
+            _ => 0,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from(ATOM),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR);
+                config.keeper = KeeperConfig {
+                    min_interval: 3_600,
+                    keepers: vec![Addr::unchecked("keeper")],
+                    bounty: Uint128::new(100_000),
+                };
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Unauthorized");
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("keeper", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+    end_operation(&mut deps);
+
+    // The keeper is paid from the sales proceeds, in the callback
+    match &res.messages.last().unwrap().msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => assert_eq!(
+            from_binary::<ExecuteMsg>(msg).unwrap(),
+            ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell {
+                basket_value: Uint128::new(23_000_000),
+                keeper: Addr::unchecked("keeper"),
+                bounty: Uint128::new(100_000),
+            })
+        ),
+        _ => panic!("Wrong message type!"),
+    }
+
+    // Not even the owner can rebalance before the interval has passed
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Rebalance is not allowed before {}",
+            env.block.time.plus_seconds(3_600).seconds()
+        )
+    );
+}
+
+#[test]
+fn rebalance_twap() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, mut env) = setup_test(&mock_pyth, current_unix_time);
+
+    // 8 USDT of INJ and 15 USDT of ATOM
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_200_000,
+            _ => 0,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from(ATOM),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR);
+                config.twap = Some(TwapConfig {
+                    slices: 2,
+                    slice_interval: 30,
+                });
+                config.keeper.bounty = Uint128::new(100_000);
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+    end_operation(&mut deps);
+
+    // Half of the 0.28 ATOM above the target
+    if let InjectiveMsg::CreateSpotMarketOrder { order, .. } =
+        &get_message_data(&res.messages, 0).msg_data
+    {
+        assert_eq!(order.market_id.as_str(), ATOMUSDT_MARKET_ID);
+        assert_eq!(order.order_info.quantity, FPDecimal::from(140_000u128));
+    } else {
+        panic!("Wrong message type!");
+    }
+
+    let res = query(deps.as_ref(), env.to_owned(), QueryMsg::GetRebalancePlan {}).unwrap();
+    let plan: Option<RebalancePlan> = from_binary(&res).unwrap();
+    assert_eq!(
+        plan,
+        Some(RebalancePlan {
+            sells: vec![PlannedSell {
+                asset: AssetInfo::NativeToken {
+                    denom: ATOM.to_string(),
+                },
+                quantity: Decimal::from_str("0.14").unwrap(),
+            }],
+            slices_left: 1,
+            slice_interval: 30,
+            next_slice_at: env.block.time.plus_seconds(30),
+            slice_bounty: Uint128::new(50_000),
+        })
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Rebalance is not allowed before {}",
+            env.block.time.plus_seconds(30).seconds()
+        )
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::AbortRebalance {},
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Unauthorized");
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::AbortRebalance {},
+    )
+    .unwrap();
+    end_operation(&mut deps);
+    assert_eq!(REBALANCE_PLAN.may_load(&deps.storage).unwrap(), None);
+
+    // A new plan, completed by its second slice
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+    end_operation(&mut deps);
+
+    // The first slice sold 0.14 ATOM for 1.7 USDT
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_060_000,
+            USDT => 1_700_000,
+            _ => 0,
+        },
+    ))));
+
+    env.block.time = env.block.time.plus_seconds(30);
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    if let InjectiveMsg::CreateSpotMarketOrder { order, .. } =
+        &get_message_data(&res.messages, 0).msg_data
+    {
+        assert_eq!(order.order_info.quantity, FPDecimal::from(140_000u128));
+    } else {
+        panic!("Wrong message type!");
+    }
+
+    // Each slice pays half of the bounty, and buys towards the basket value after the first one
+    match &res.messages[1].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => assert!(matches!(
+            from_binary::<ExecuteMsg>(msg).unwrap(),
+            ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell { basket_value, bounty, .. })
+                if basket_value == Uint128::new(22_950_000) && bounty == Uint128::new(50_000)
+        )),
+        _ => panic!("Wrong message type!"),
+    }
+    assert_eq!(REBALANCE_PLAN.may_load(&deps.storage).unwrap(), None);
+    end_operation(&mut deps);
+
+    // Nothing to sell, the rebalance takes a single slice and pays the whole bounty
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 640_000,
+            USDT => 4_000_000,
+            _ => 0,
+        },
+    ))));
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    match &res.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => assert!(matches!(
+            from_binary::<ExecuteMsg>(msg).unwrap(),
+            ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell { bounty, .. })
+                if bounty == Uint128::new(100_000)
+        )),
+        _ => panic!("Wrong message type!"),
+    }
+    assert_eq!(REBALANCE_PLAN.may_load(&deps.storage).unwrap(), None);
+}
+
+#[test]
+fn rebalance_passive() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    // 8 USDT of INJ, 15 USDT of ATOM and 1 USDT
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_200_000,
+            USDT => 1_000_000,
+            _ => 0,
+        },
+    ))));
+    deps.querier.subaccount_deposit_response_handler =
+        Some(Box::new(create_subaccount_deposit_handler(|_| 100)));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from(ATOM),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR);
+                config.rebalance_mode = RebalanceMode::Passive { offset_bps: 10 };
+                config.keeper.bounty = Uint128::new(100_000);
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    // The mocked subaccount deposits of USDT, INJ and ATOM are withdrawn first, then the keeper
+    // is paid its bounty
+    assert_eq!(res.messages.len(), 5);
+    for position in 0..3 {
+        assert!(matches!(
+            get_message_data(&res.messages, position).msg_data,
+            InjectiveMsg::Withdraw { .. }
+        ));
+    }
+    assert_eq!(
+        res.messages[3].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("anyone"),
+            amount: coins(100_000, USDT),
+        })
+    );
+
+    if let InjectiveMsg::BatchUpdateOrders {
+        spot_market_ids_to_cancel_all,
+        spot_orders_to_create,
+        ..
+    } = &get_message_data(&res.messages, 4).msg_data
+    {
+        assert_eq!(
+            spot_market_ids_to_cancel_all,
+            &vec![
+                MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                MarketId::new(ATOMUSDT_MARKET_ID).unwrap()
+            ]
+        );
+
+        // Targets of 12 USDT, the USDT held included: 0.24 ATOM sold above the oracle price, and
+        // the 0.5 INJ missing bought below it up to the 0.9 USDT left after the bounty
+        assert_eq!(spot_orders_to_create.len(), 2);
+        assert_eq!(spot_orders_to_create[0].order_type, OrderType::SellPo);
+        assert_eq!(
+            spot_orders_to_create[0].order_info.quantity,
+            FPDecimal::from(240_000u128)
+        );
+        assert_eq!(spot_orders_to_create[1].order_type, OrderType::BuyPo);
+        assert_eq!(
+            spot_orders_to_create[1].order_info.quantity,
+            FPDecimal::from(112_000_000_000_000_000u128)
+        );
+    } else {
+        panic!("Wrong message type!");
+    }
+
+    // The operation ends with its orders resting on the book
+    let operations: Vec<Operation> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.to_owned(),
+            QueryMsg::GetOperations {
+                start_before: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].kind, OperationKind::Rebalance);
+    assert_eq!(
+        operations[0]
+            .fills
+            .iter()
+            .map(|f| (f.market_id.as_str(), f.side, f.status))
+            .collect::<Vec<_>>(),
+        vec![
+            (ATOMUSDT_MARKET_ID, OrderSide::Sell, FillStatus::Resting),
+            (INJUSDT_MARKET_ID, OrderSide::Buy, FillStatus::Resting),
+        ]
+    );
+    assert_eq!(OPERATION_IN_PROGRESS.may_load(&deps.storage).unwrap(), None);
+}
+
+#[test]
+fn rebalance() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_000_000,
+            _ => 1_000_000_000_000_000,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from("inj"),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: {
+                            AssetInfo::NativeToken {
+                                denom: String::from(ATOM),
+                            }
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &vec![]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                let mock_address = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
+                config.lp_token = mock_address;
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    let asset = Asset {
+        amount: Uint128::from(1_000_000u128),
+        info: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+    };
+    let info = mock_info(
+        "creator",
+        &vec![Coin {
+            amount: asset.amount.to_owned(),
+            denom: String::from(USDT),
+        }],
+    );
+
+    let msg = ExecuteMsg::Rebalance {};
+
+    let res = execute(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    println!("{:?}", res);
+}
+
+#[test]
+fn update_config() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from("usdt"),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 100,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+        pyth_contract_addr: Some(String::from("new-pyth-contract-addr")),
+        deposit_asset: Some(AssetInfo::NativeToken {
+            denom: String::from("usdc"),
+        }),
+        ..UpdateConfigMsg::default()
+    });
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            keeper: Some(KeeperConfigMsg {
+                min_interval: 0,
+                keepers: vec![String::from("Keeper")],
+                bounty: Uint128::zero(),
+            }),
+            ..UpdateConfigMsg::default()
+        }),
+    )
+    .unwrap_err();
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            fees: Some(FeeConfigMsg {
+                collector: String::from("Collector"),
+                management_fee_bps: 0,
+                performance_fee_bps: 0,
+                performance_fee_period: 0,
+                entry_fee_bps: 0,
+                exit_fee_bps: 0,
+                fee_exempt: vec![],
+            }),
+            ..UpdateConfigMsg::default()
+        }),
+    )
+    .unwrap_err();
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        msg,
+    )
+    .unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.to_owned(),
+        QueryMsg::GetPendingChanges {
+            start_after: None,
+            limit: None,
+        },
+    )
+    .unwrap();
+    let pending_changes: Vec<PendingChange> = from_binary(&res).unwrap();
+    assert_eq!(pending_changes.len(), 1);
+    assert_eq!(pending_changes[0].id, 1);
+    assert_eq!(
+        pending_changes[0].effective_at,
+        env.block.time.plus_seconds(100)
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ApplyChange { id: 1 },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::TimelockNotExpired { id: 1, .. }
+    ));
+
+    env.block.time = env.block.time.plus_seconds(100);
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ApplyChange { id: 1 },
+    )
+    .unwrap();
+
+    let config = CONFIG.load(&deps.storage).unwrap();
+    assert_eq!(
+        config.pyth_contract_addr,
+        Addr::unchecked("new-pyth-contract-addr")
+    );
+    assert_eq!(
+        config.deposit_asset,
+        AssetInfo::NativeToken {
+            denom: String::from("usdc"),
+        }
+    );
+    assert!(PENDING_CHANGES.is_empty(&deps.storage));
+}
+
+#[test]
+fn cancel_change() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from("usdt"),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 100,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            timelock_delay: Some(0),
+            ..UpdateConfigMsg::default()
+        }),
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::CancelChange { id: 1 },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::CancelChange { id: 1 },
+    )
+    .unwrap();
+
+    assert!(PENDING_CHANGES.is_empty(&deps.storage));
+    assert_eq!(CONFIG.load(&deps.storage).unwrap().timelock_delay, 100);
+}
+
+#[test]
+fn transfer_ownership() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from("usdt"),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from("pyth-contract-addr"),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::ProposeNewOwner {
+        owner: String::from("new-owner"),
+        expires_in: 100,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        msg,
+    )
+    .unwrap();
+
+    // Proposal alone does not change the owner
+    let config = CONFIG.load(&deps.storage).unwrap();
+    assert_eq!(config.owner, Addr::unchecked("creator"));
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    env.block.time = env.block.time.plus_seconds(101);
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("new-owner", &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::OwnershipProposalExpired {}));
+
+    env.block.time = env.block.time.minus_seconds(1);
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("new-owner", &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap();
+
+    let config = CONFIG.load(&deps.storage).unwrap();
+    assert_eq!(config.owner, Addr::unchecked("new-owner"));
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig(UpdateConfigMsg::default()),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+}
+
+#[test]
+fn basket_governance() {
+    let current_unix_time = 10_000_000;
+    let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: String::from("inj"),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: String::from(ATOM),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    let inj = AssetInfo::NativeToken {
+        denom: String::from("inj"),
+    };
+    let atom = AssetInfo::NativeToken {
+        denom: String::from(ATOM),
+    };
+
+    // Reweight
+    let msg = ExecuteMsg::UpdateAssetWeight {
+        asset: inj.to_owned(),
+        weight: Uint128::from(3u128),
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        msg.to_owned(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        msg,
+    )
+    .unwrap();
+    apply_pending_change(&mut deps, &env, 1).unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateAssetWeight {
+            asset: inj.to_owned(),
+            weight: Uint128::zero(),
+        },
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("RetireAsset"));
+
+    // Add
+    let weth = BasketAsset {
+        asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: String::from("weth"),
+            },
+            amount: Uint128::zero(),
+        },
+        pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        weight: Uint128::from(2u128),
+        spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+        slippage: None,
+        drift_band_bps: None,
+        oracle: None,
+    };
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::AddAsset {
+            basket_asset: weth.to_owned(),
+        },
+    )
+    .unwrap();
+    apply_pending_change(&mut deps, &env, 2).unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::AddAsset {
+            basket_asset: weth.to_owned(),
+        },
+    )
+    .unwrap();
+    let err = apply_pending_change(&mut deps, &env, 3).unwrap_err();
+    assert!(err.to_string().contains("already exists"));
+
+    // Retire
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetireAsset {
+            asset: atom.to_owned(),
+        },
+    )
+    .unwrap();
+    apply_pending_change(&mut deps, &env, 4).unwrap();
+
+    let basket = BASKET.load(&deps.storage).unwrap();
+    assert_eq!(
+        basket
+            .assets
+            .iter()
+            .map(|b| (b.asset.info.to_owned(), b.weight))
+            .collect::<Vec<(AssetInfo, Uint128)>>(),
+        vec![
+            (inj.to_owned(), Uint128::from(3u128)),
+            (atom, Uint128::zero()),
+            (weth.asset.info.to_owned(), Uint128::from(2u128)),
+        ]
+    );
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetireAsset { asset: inj },
+    )
+    .unwrap();
+    apply_pending_change(&mut deps, &env, 5).unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetireAsset {
+            asset: weth.asset.info,
+        },
+    )
+    .unwrap();
+    let err = apply_pending_change(&mut deps, &env, 6).unwrap_err();
+    assert!(err.to_string().contains("last active basket asset"));
+}
+
+#[test]
+fn rebalance_retired_asset() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 1_000_000,
+            _ => 1_000_000_000_000_000,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: String::from("inj"),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: String::from(ATOM),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetireAsset {
+            asset: AssetInfo::NativeToken {
+                denom: String::from(ATOM),
+            },
+        },
+    )
+    .unwrap();
+    apply_pending_change(&mut deps, &env, 1).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+    end_operation(&mut deps);
+
+    // INJ is the only basket asset left, so only ATOM is sold
+    assert_eq!(res.messages.len(), 2);
+    if let InjectiveMsg::CreateSpotMarketOrder { order, .. } =
+        &get_message_data(&res.messages, 0).msg_data
+    {
+        assert_eq!(order.market_id.as_str(), ATOMUSDT_MARKET_ID);
+        assert_eq!(order.order_type, OrderType::SellAtomic);
+    } else {
+        panic!("Wrong message type!");
+    }
+
+    // A retired asset with nothing left is within its band
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            _ => 0,
+        },
+    ))));
+
+    let res = query(deps.as_ref(), env.to_owned(), QueryMsg::GetAssetWeights {}).unwrap();
+    let weights: Vec<AssetWeightResponse> = from_binary(&res).unwrap();
+    assert!(weights[1].target_weight.is_zero() && weights[1].value.is_zero());
+    assert!(weights.iter().all(|w| w.within_band));
+
+    // Once sold, the retired asset is removed from the basket
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_000_000_000_000_000_000,
+            ATOM => 999,
+            _ => 1_000_000_000_000_000,
+        },
+    ))));
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    assert!(res
+        .attributes
+        .iter()
+        .any(|a| a.key == "removed_asset" && a.value == ATOM));
+
+    let basket = BASKET.load(&deps.storage).unwrap();
+    assert_eq!(basket.assets.len(), 1);
+    assert_eq!(
+        basket.assets[0].asset.info,
+        AssetInfo::NativeToken {
+            denom: String::from("inj"),
+        }
+    );
+}
+
+#[test]
+fn pause() {
+    let current_unix_time = 10_000_000;
+    let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: USDT.to_owned(),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: Some(String::from("guardian")),
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    let pause_msg = ExecuteMsg::SetPause {
+        deposit: Some(true),
+        withdraw: None,
+        rebalance: Some(true),
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        pause_msg.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("guardian", &[]),
+        pause_msg,
+    )
+    .unwrap();
+
+    let pause_state: PauseState =
+        from_binary(&query(deps.as_ref(), env.to_owned(), QueryMsg::GetPauseState {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        pause_state,
+        PauseState {
+            deposit: true,
+            withdraw: false,
+            rebalance: true,
+        }
+    );
+
+    let deposit_msg = ExecuteMsg::Deposit {
+        asset: Asset {
+            amount: Uint128::from(1u128),
+            info: AssetInfo::NativeToken {
+                denom: USDT.to_owned(),
+            },
+        },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &coins(1, USDT.to_owned())),
+        deposit_msg,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::DepositPaused {}));
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::RebalancePaused {}));
+
+    // The guardian can only pause
+    let unpause_msg = ExecuteMsg::SetPause {
+        deposit: Some(false),
+        withdraw: None,
+        rebalance: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("guardian", &[]),
+        unpause_msg.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        unpause_msg,
+    )
+    .unwrap();
+
+    let pause_state: PauseState =
+        from_binary(&query(deps.as_ref(), env.to_owned(), QueryMsg::GetPauseState {}).unwrap())
+            .unwrap();
+    assert!(!pause_state.deposit);
+    assert!(pause_state.rebalance);
+
+    // The owner removes the guardian
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            guardian: Some(String::from("new-guardian")),
+            clear_guardian: Some(true),
+            ..UpdateConfigMsg::default()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Generic error: Cannot both set and clear the guardian"
+    );
+
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            clear_guardian: Some(true),
+            ..UpdateConfigMsg::default()
+        }),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ApplyChange { id: 1 },
+    )
+    .unwrap();
+    assert_eq!(CONFIG.load(&deps.storage).unwrap().guardian, None);
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("guardian", &[]),
+        ExecuteMsg::SetPause {
+            deposit: Some(true),
+            withdraw: None,
+            rebalance: None,
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+}
+
+#[test]
+fn management_fee() {
+    let current_unix_time = 10_000_000;
+    let mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let (mut deps, mut env) = setup_test(&mock_pyth, current_unix_time);
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: USDT.to_owned(),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 200,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    // Half a year at 2% per year on the 100 LP tokens in supply
+    env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR / 2);
+
+    let pending_fees: PendingFeesResponse =
+        from_binary(&query(deps.as_ref(), env.to_owned(), QueryMsg::GetPendingFees {}).unwrap())
+            .unwrap();
+    assert_eq!(pending_fees.management_fee, Uint128::new(1));
+
+    let msg = ExecuteMsg::Deposit {
+        asset: Asset {
+            amount: Uint128::from(1u128),
+            info: AssetInfo::NativeToken {
+                denom: USDT.to_owned(),
+            },
+        },
+        min_lp_out: None,
+        deadline: None,
+        max_slippage_bps: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("anyone", &coins(1, USDT.to_owned())),
+        msg,
+    )
+    .unwrap();
+
+    // The fee is minted before the deposit is priced
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: LP_TOKEN_ADDR.to_owned(),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Mint {
+                recipient: String::from("collector"),
+                amount: Uint128::new(1),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    let pending_fees: PendingFeesResponse =
+        from_binary(&query(deps.as_ref(), env.to_owned(), QueryMsg::GetPendingFees {}).unwrap())
+            .unwrap();
+    assert!(pending_fees.management_fee.is_zero());
+}
+
+#[test]
+fn deposit_in_kind() {
+    let current_unix_time = 10_000_000;
+    let mut mock_pyth = MockPyth::new(Duration::from_secs(60), Coin::new(1, "foo"), &[]);
+    let price_feed_inj = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+        Price {
+            price: 800000000,
+            conf: 10,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 800000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+    let price_feed_atom = PriceFeed::new(
+        PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+        Price {
+            price: 1250000000,
+            conf: 20,
+            expo: -8,
+            publish_time: current_unix_time,
+        },
+    );
+
+    mock_pyth.add_feed(price_feed_inj);
+    mock_pyth.add_feed(price_feed_atom);
+
+    let (mut deps, env) = setup_test(&mock_pyth, current_unix_time);
+    // Holdings of 1_000 INJ and 2_000 ATOM, plus the coins sent below
+    deps.querier.balance_query_handler = Some(Box::new(create_balance_bank_handler(Some(
+        |denom| match denom {
+            "inj" => 1_100_000_000_000_000_000_000,
+            ATOM => 2_050_000_000,
+            _ => 0,
+        },
+    ))));
+
+    let msg = InstantiateMsg {
+        etf_token_code_id: 1,
+        etf_token_name: String::from("ER-Strategy-1"),
+        deposit_asset: AssetInfo::NativeToken {
+            denom: String::from(USDT),
+        },
+        cw20_adapter: None,
+        pyth_contract_addr: String::from(PYTH_CONTRACT_ADDR),
+        basket: Basket {
+            assets: vec![
+                BasketAsset {
+                    asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: String::from("inj"),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_INJ).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(INJUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+                BasketAsset {
+                    asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: String::from(ATOM),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    pyth_price_feed: PriceIdentifier::from_hex(PRICE_ID_ATOM).unwrap(),
+                    weight: Uint128::from(1u128),
+                    spot_market_id: MarketId::new(ATOMUSDT_MARKET_ID).unwrap(),
+                    slippage: None,
+                    drift_band_bps: None,
+                    oracle: None,
+                },
+            ],
+            drift_band_bps: 0,
+        },
+        timelock_delay: 0,
+        guardian: None,
+        fees: FeeConfigMsg {
+            collector: String::from("collector"),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            performance_fee_period: 0,
+            entry_fee_bps: 0,
+            exit_fee_bps: 0,
+            fee_exempt: vec![],
+        },
+        slippage: SlippageConfig {
+            deposit_bps: 500,
+            withdraw_bps: 500,
+            rebalance_bps: 1_500,
+            max_price_impact_bps: None,
+        },
+        keeper: KeeperConfigMsg {
+            min_interval: 0,
+            keepers: vec![],
+            bounty: Uint128::zero(),
+        },
+        twap: None,
+        rebalance_mode: RebalanceMode::Atomic,
+        order_failure: OrderFailurePolicy::Abort,
+        oracle: OracleConfig {
+            max_age: 60,
+            max_conf_bps: 100,
+            max_deviation_bps: None,
+        },
+        pricing: PricingConfig {
+            deposit: PricingMode::Spot,
+            withdraw: PricingMode::Spot,
+            rebalance: PricingMode::Spot,
+        },
+    };
+    let info = mock_info("creator", &[]);
+
+    let _res = instantiate(deps.as_mut(), env.to_owned(), info, msg).unwrap();
+
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.lp_token = Addr::unchecked(LP_TOKEN_ADDR.to_owned());
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info("allocator", &coins(100, USDT)),
+        ExecuteMsg::DepositInKind {},
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("Generic error: Deposit other tokens 100{}", USDT)
+    );
+
+    // 10% of the INJ holdings but only 2.5% of the ATOM holdings
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info(
+            "allocator",
+            &[
+                coin(100_000_000_000_000_000_000, "inj"),
+                coin(50_000_000, ATOM),
+            ],
+        ),
+        ExecuteMsg::DepositInKind {},
+    )
+    .unwrap();
+
+    let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+    assert_eq!(
+        messages,
+        vec![
+            // 2.5% of the 100 LP tokens in supply
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: LP_TOKEN_ADDR.to_owned(),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Mint {
+                    recipient: String::from("allocator"),
+                    amount: Uint128::new(2),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("allocator"),
+                amount: coins(75_000_000_000_000_000_000, "inj"),
+            }),
+        ]
+    );
+
+    // The entry fee is taken in kind from the accepted assets
+    CONFIG
+        .update(
+            &mut deps.storage,
+            |mut config| -> Result<_, ContractError> {
+                config.fees.entry_fee_bps = 100;
+                Ok(config)
+            },
+        )
+        .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.to_owned(),
+        mock_info(
+            "allocator",
+            &[
+                coin(100_000_000_000_000_000_000, "inj"),
+                coin(50_000_000, ATOM),
+            ],
+        ),
+        ExecuteMsg::DepositInKind {},
+    )
+    .unwrap();
+
+    let messages: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+    assert_eq!(
+        messages,
+        vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: LP_TOKEN_ADDR.to_owned(),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Mint {
+                    recipient: String::from("allocator"),
+                    amount: Uint128::new(2),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("collector"),
+                amount: coins(250_000_000_000_000_000, "inj"),
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("allocator"),
+                amount: coins(75_000_000_000_000_000_000, "inj"),
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("collector"),
+                amount: coins(500_000, ATOM),
+            }),
+        ]
+    );
+
+    // Not priced on a stale feed
+    let mut env = env;
+    env.block.time = env.block.time.plus_seconds(61);
+    let err = execute(
+        deps.as_mut(),
+        env,
+        mock_info(
+            "allocator",
+            &[
+                coin(100_000_000_000_000_000_000, "inj"),
+                coin(50_000_000, ATOM),
+            ],
+        ),
+        ExecuteMsg::DepositInKind {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::StalePrice { .. }));
 }
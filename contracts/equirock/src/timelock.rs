@@ -0,0 +1,242 @@
+use cosmwasm_std::{Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult};
+use injective_cosmwasm::{InjectiveMsgWrapper, InjectiveQueryWrapper};
+
+use crate::{
+    drift::validate_drift_band,
+    execute::{add_asset, retire_asset, update_asset_weight, update_config, update_drift_band},
+    helpers::{check_quote_denom, quote_denom},
+    msg::UpdateConfigMsg,
+    state::{
+        ConfigChange, ConfigUpdate, PendingChange, BASKET, CONFIG, PENDING_CHANGES,
+        PENDING_CHANGE_ID,
+    },
+    ContractError,
+};
+
+/// Queues an `UpdateConfig`, validating its addresses into the stored `ConfigUpdate`.
+pub fn queue_config_update(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+    msg: UpdateConfigMsg,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let clear_guardian = msg.clear_guardian.unwrap_or(false);
+    if clear_guardian && msg.guardian.is_some() {
+        return Err(StdError::generic_err("Cannot both set and clear the guardian").into());
+    }
+
+    let update = ConfigUpdate {
+        pyth_contract_addr: msg
+            .pyth_contract_addr
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        deposit_asset: msg.deposit_asset,
+        cw20_adapter: msg
+            .cw20_adapter
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        guardian: msg
+            .guardian
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        clear_guardian,
+        timelock_delay: msg.timelock_delay,
+        fees: msg.fees.map(|fees| fees.validate(deps.api)).transpose()?,
+        slippage: msg.slippage,
+        keeper: msg
+            .keeper
+            .map(|keeper| keeper.validate(deps.api))
+            .transpose()?,
+        twap: msg.twap,
+        rebalance_mode: msg.rebalance_mode,
+        order_failure: msg.order_failure,
+        oracle: msg.oracle,
+        pricing: msg.pricing,
+    };
+
+    queue_change(deps, env, info, ConfigChange::UpdateConfig(update))
+}
+
+pub fn queue_change(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+    change: ConfigChange,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    validate_change(deps.as_ref(), &change)?;
+
+    let id = PENDING_CHANGE_ID
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        + 1;
+    PENDING_CHANGE_ID.save(deps.storage, &id)?;
+
+    let effective_at = env.block.time.plus_seconds(config.timelock_delay);
+
+    PENDING_CHANGES.save(
+        deps.storage,
+        id,
+        &PendingChange {
+            id,
+            change,
+            effective_at,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "queue_change".to_string()),
+        ("change_id", id.to_string()),
+        ("effective_at", effective_at.seconds().to_string()),
+    ]))
+}
+
+pub fn apply_change(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    id: u64,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let pending_change = PENDING_CHANGES.load(deps.storage, id)?;
+
+    if env.block.time < pending_change.effective_at {
+        return Err(ContractError::TimelockNotExpired {
+            id,
+            effective_at: pending_change.effective_at.seconds(),
+        });
+    }
+
+    PENDING_CHANGES.remove(deps.storage, id);
+
+    let res = match pending_change.change {
+        ConfigChange::UpdateConfig(msg) => update_config(deps, env, msg),
+        ConfigChange::UpdateAssetWeight { asset, weight } => {
+            update_asset_weight(deps, asset, weight)
+        }
+        ConfigChange::AddAsset { basket_asset } => add_asset(deps, basket_asset),
+        ConfigChange::RetireAsset { asset } => retire_asset(deps, asset),
+        ConfigChange::UpdateDriftBand {
+            asset,
+            drift_band_bps,
+        } => update_drift_band(deps, asset, drift_band_bps),
+    }?;
+
+    Ok(res.add_attribute("change_id", id.to_string()))
+}
+
+pub fn cancel_change(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if !PENDING_CHANGES.has(deps.storage, id) {
+        return Err(StdError::not_found(format!("Pending change {}", id)).into());
+    }
+
+    PENDING_CHANGES.remove(deps.storage, id);
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "cancel_change".to_string()),
+        ("change_id", id.to_string()),
+    ]))
+}
+
+/// Validates the parts of a change which do not depend on the state at the time it is applied.
+fn validate_change(deps: Deps<InjectiveQueryWrapper>, change: &ConfigChange) -> StdResult<()> {
+    match change {
+        ConfigChange::UpdateConfig(msg) => {
+            if let Some(deposit_asset) = &msg.deposit_asset {
+                deposit_asset.check(deps.api)?;
+            }
+
+            if msg.deposit_asset.is_some() || msg.cw20_adapter.is_some() {
+                let config = CONFIG.load(deps.storage)?;
+                let deposit_asset = msg.deposit_asset.as_ref().unwrap_or(&config.deposit_asset);
+                let cw20_adapter = msg.cw20_adapter.as_ref().or(config.cw20_adapter.as_ref());
+
+                quote_denom(deposit_asset, cw20_adapter)?;
+                for basket_asset in BASKET.load(deps.storage)?.assets.iter() {
+                    check_quote_denom(
+                        &deps.querier,
+                        deposit_asset,
+                        cw20_adapter,
+                        &basket_asset.spot_market_id,
+                    )?;
+                }
+            }
+
+            if let Some(slippage) = &msg.slippage {
+                slippage.validate()?;
+            }
+
+            if let Some(twap) = &msg.twap {
+                twap.validate()?;
+            }
+
+            if let Some(rebalance_mode) = &msg.rebalance_mode {
+                rebalance_mode.validate()?;
+            }
+
+            if let Some(oracle) = &msg.oracle {
+                oracle.validate()?;
+            }
+        }
+        ConfigChange::UpdateAssetWeight { weight, .. } => {
+            if weight.is_zero() {
+                return Err(StdError::generic_err(
+                    "Use RetireAsset to remove an asset from the basket",
+                ));
+            }
+        }
+        ConfigChange::AddAsset { basket_asset } => {
+            if !basket_asset.asset.amount.is_zero() {
+                return Err(StdError::generic_err(format!(
+                    "Non-zero basket asset {}",
+                    basket_asset.asset.info
+                )));
+            }
+
+            if basket_asset.weight.is_zero() {
+                return Err(StdError::generic_err(
+                    "Basket asset weight must be non-zero",
+                ));
+            }
+
+            if let Some(slippage) = &basket_asset.slippage {
+                slippage.validate()?;
+            }
+
+            if let Some(oracle) = &basket_asset.oracle {
+                oracle.validate()?;
+            }
+
+            if let Some(drift_band_bps) = basket_asset.drift_band_bps {
+                validate_drift_band(drift_band_bps)?;
+            }
+
+            let config = CONFIG.load(deps.storage)?;
+            check_quote_denom(
+                &deps.querier,
+                &config.deposit_asset,
+                config.cw20_adapter.as_ref(),
+                &basket_asset.spot_market_id,
+            )?;
+        }
+        ConfigChange::RetireAsset { .. } => {}
+        ConfigChange::UpdateDriftBand { drift_band_bps, .. } => {
+            validate_drift_band(*drift_band_bps)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use cosmwasm_std::{
+    to_binary, Decimal, DepsMut, Env, Event, MessageInfo, Response, StdError, StdResult, SubMsg,
+    WasmMsg,
+};
+use injective_cosmwasm::{
+    get_default_subaccount_id_for_checked_address, InjectiveMsgWrapper, InjectiveQuerier,
+    InjectiveQueryWrapper,
+};
+
+use crate::{
+    execute::rebalance_sell_order,
+    ledger::{record_order, start_operation},
+    msg::{CallbackMsg, ExecuteMsg},
+    oracle::check_oracle_deviation,
+    querier::{query_decimals, query_holdings},
+    query::{basket_asset_limit_price, basket_value_usdt},
+    reply::order_submsg,
+    state::{
+        Config, OperationKind, OrderSide, RebalancePlan, TwapConfig, BASKET, CONFIG, REBALANCE_PLAN,
+    },
+    ContractError,
+};
+
+/// Maximum number of slices of a rebalance.
+pub const MAX_TWAP_SLICES: u64 = 100;
+
+impl TwapConfig {
+    pub fn validate(&self) -> StdResult<()> {
+        if self.slices == 0 || self.slices > MAX_TWAP_SLICES {
+            return Err(StdError::generic_err(format!(
+                "TWAP slices must be between 1 and {}",
+                MAX_TWAP_SLICES
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Sells the next slice of the rebalance plan, then buys the underweight assets with the
+/// proceeds like a single rebalance does, towards the current basket value.
+pub fn rebalance_step(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+    mut plan: RebalancePlan,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    if env.block.time < plan.next_slice_at {
+        return Err(ContractError::RebalanceCooldown {
+            next_rebalance: plan.next_slice_at.seconds(),
+        });
+    }
+
+    let operation_id = start_operation(deps.storage, &env, OperationKind::Rebalance, &info.sender)?;
+
+    let basket = BASKET.load(deps.storage)?;
+    let basket_value = basket_value_usdt(
+        &deps.querier,
+        &env,
+        &config,
+        &basket,
+        config.pricing.pricing(OperationKind::Rebalance),
+    )?;
+
+    let contract = &env.contract.address;
+    let subaccount_id = get_default_subaccount_id_for_checked_address(contract);
+    let injective_querier = InjectiveQuerier::new(&deps.querier);
+
+    let mut submessages: Vec<SubMsg<InjectiveMsgWrapper>> = vec![];
+    let mut log: Vec<String> = vec![];
+    let mut events: Vec<Event> = vec![];
+
+    for planned_sell in &plan.sells {
+        // Retired and removed since the plan started
+        let (index, basket_asset) = match basket
+            .assets
+            .iter()
+            .enumerate()
+            .find(|(_, b)| b.asset.info == planned_sell.asset)
+        {
+            Some(found) => found,
+            None => continue,
+        };
+
+        let market = match injective_querier
+            .query_spot_market(&basket_asset.spot_market_id)?
+            .market
+        {
+            Some(market) => market,
+            None => continue,
+        };
+
+        let amount = query_holdings(&deps.querier, &basket_asset.asset.info, contract)?;
+        let decimals = query_decimals(&deps.querier, &basket_asset.asset.info);
+        let current_quantity = Decimal::from_atomics(amount, decimals as u32)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+
+        let quantity = planned_sell.quantity.min(current_quantity);
+        if quantity.is_zero() {
+            continue;
+        }
+
+        if let Some(event) = check_oracle_deviation(
+            &deps.querier,
+            &env,
+            &config,
+            basket_asset,
+            &market,
+            decimals,
+        )? {
+            events.push(event);
+        }
+
+        let limit_price = basket_asset_limit_price(
+            &deps.querier,
+            &env,
+            &config,
+            basket_asset,
+            OperationKind::Rebalance,
+            OrderSide::Sell,
+        )?;
+
+        if let Some(order_msg) = rebalance_sell_order(
+            &deps.querier,
+            &config,
+            basket_asset,
+            &market,
+            limit_price,
+            quantity,
+            decimals,
+            &subaccount_id,
+            contract,
+        )? {
+            log.push(format!("order_msg {:?}", order_msg));
+            record_order(
+                deps.storage,
+                &basket_asset.asset.info,
+                &market.market_id,
+                OrderSide::Sell,
+            )?;
+            submessages.push(order_submsg(order_msg, index));
+        }
+    }
+
+    plan.slices_left -= 1;
+    if plan.slices_left == 0 {
+        REBALANCE_PLAN.remove(deps.storage);
+    } else {
+        plan.next_slice_at = env.block.time.plus_seconds(plan.slice_interval);
+        REBALANCE_PLAN.save(deps.storage, &plan)?;
+    }
+
+    let after_rebalance_msg = WasmMsg::Execute {
+        contract_addr: contract.to_string(),
+        msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::AfterRebalanceSell {
+            basket_value,
+            keeper: info.sender,
+            bounty: plan.slice_bounty,
+        }))?,
+        funds: vec![],
+    };
+
+    Ok(Response::new()
+        .add_attributes(vec![
+            ("action", "rebalance_step".to_string()),
+            ("operation_id", operation_id.to_string()),
+            ("slices_left", plan.slices_left.to_string()),
+            ("log", format!("{:?}", log)),
+        ])
+        .add_events(events)
+        .add_submessages(submessages)
+        .add_message(after_rebalance_msg))
+}
+
+pub fn abort_rebalance(
+    deps: DepsMut<InjectiveQueryWrapper>,
+    info: MessageInfo,
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner && config.guardian.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let plan = REBALANCE_PLAN
+        .may_load(deps.storage)?
+        .ok_or_else(|| StdError::not_found("Rebalance plan"))?;
+
+    REBALANCE_PLAN.remove(deps.storage);
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "abort_rebalance".to_string()),
+        ("slices_left", plan.slices_left.to_string()),
+    ]))
+}
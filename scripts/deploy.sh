@@ -19,6 +19,37 @@ INSTANTIATE_MSG=$(cat <<-END
           }
         },
         "pyth_contract_addr": "inj1z60tg0tekdzcasenhuuwq3htjcd5slmgf7gpez",
+        "timelock_delay": 86400,
+        "fees": {
+          "collector": "inj1e79v7eyl9yxjnmperuzzfd3w7q495na9hq4xck",
+          "management_fee_bps": 0,
+          "performance_fee_bps": 0,
+          "performance_fee_period": 604800,
+          "entry_fee_bps": 0,
+          "exit_fee_bps": 0,
+          "fee_exempt": []
+        },
+        "slippage": {
+          "deposit_bps": 500,
+          "withdraw_bps": 500,
+          "rebalance_bps": 1500
+        },
+        "keeper": {
+          "min_interval": 3600,
+          "keepers": [],
+          "bounty": "100000"
+        },
+        "rebalance_mode": "atomic",
+        "order_failure": "abort",
+        "oracle": {
+          "max_age": 60,
+          "max_conf_bps": 100
+        },
+        "pricing": {
+          "deposit": "spot",
+          "withdraw": "spot",
+          "rebalance": "spot"
+        },
         "basket": {
           "assets": [{
             "asset": {
@@ -44,7 +75,8 @@ INSTANTIATE_MSG=$(cat <<-END
             "weight": "1",
             "pyth_price_feed": "2d9315a88f3019f8efa88dfe9c0f0843712da0bac814461e27733f6b83eb51b3",
             "spot_market_id": "0x0611780ba69656949525013d947713300f56c37b6175e02f26bffa495c3208fe"
-          }]
+          }],
+          "drift_band_bps": 200
         }
     }
 END